@@ -1,6 +1,4 @@
-use std::collections::{HashMap, HashSet};
-
-use aoc2024::common::read_input;
+use aoc2024::common::{graph::Graph, has_flag, read_input};
 
 const DAY: u32 = 5;
 
@@ -10,23 +8,33 @@ pub fn main() {
     println!("Day {DAY}, part 1: {part1_result}");
     let part2_result = part2(&input);
     println!("Day {DAY}, part 2: {part2_result}");
+
+    if has_flag("--explain") {
+        let (rules, updates) = &input;
+        for update in updates {
+            match validate(rules, update) {
+                Validation::Valid => {}
+                Validation::Reordered { violations, order } => {
+                    println!("{update:?} breaks {violations:?}, fixed as {order:?}")
+                }
+                Validation::Cyclic { violations, cycle } => {
+                    println!("{update:?} breaks {violations:?}, rules cycle through {cycle:?}")
+                }
+            }
+        }
+    }
 }
 
 // Parse input
-fn process_input(input: Vec<String>) -> (HashMap<u32, Vec<u32>>, Vec<Vec<u32>>) {
-    let splitted: Vec<&[String]> = input.split(|v| v == "").collect();
+fn process_input(input: Vec<String>) -> (Graph<u32>, Vec<Vec<u32>>) {
+    let splitted: Vec<&[String]> = input.split(|v| v.is_empty()).collect();
 
-    // Create rules map
-    let mut rules_map = HashMap::new();
-    splitted[0]
-        .iter()
-        .map(|value| {
-            let parts: Vec<&str> = value.split("|").collect();
-            let a = parts[0].parse::<u32>().unwrap();
-            let b = parts[1].parse::<u32>().unwrap();
-            (a, b)
-        })
-        .for_each(|(left, right)| rules_map.entry(left).or_insert_with(Vec::new).push(right));
+    // Create rules graph: an edge a -> b means a must come before b
+    let mut rules = Graph::directed();
+    splitted[0].iter().for_each(|value| {
+        let (a, b) = value.split_once('|').unwrap();
+        rules.add_edge(a.parse::<u32>().unwrap(), b.parse::<u32>().unwrap());
+    });
 
     // Create updates part
     let updates = splitted[1]
@@ -37,11 +45,16 @@ fn process_input(input: Vec<String>) -> (HashMap<u32, Vec<u32>>, Vec<Vec<u32>>)
                 .collect::<Vec<u32>>()
         })
         .collect::<Vec<Vec<u32>>>();
-    (rules_map, updates)
+
+    // Pages without any rule still get a vertex
+    updates.iter().flatten().for_each(|&page| {
+        rules.intern(page);
+    });
+    (rules, updates)
 }
 
 // Parse sample input
-fn _sample_input() -> (HashMap<u32, Vec<u32>>, Vec<Vec<u32>>) {
+fn _sample_input() -> (Graph<u32>, Vec<Vec<u32>>) {
     let data = "47|53
 97|13
 97|61
@@ -77,89 +90,108 @@ fn _sample_input() -> (HashMap<u32, Vec<u32>>, Vec<Vec<u32>>) {
 }
 
 // Parse day's input
-fn day_input() -> (HashMap<u32, Vec<u32>>, Vec<Vec<u32>>) {
+fn day_input() -> (Graph<u32>, Vec<Vec<u32>>) {
     process_input(read_input(DAY))
 }
 
-fn part1(input: &(HashMap<u32, Vec<u32>>, Vec<Vec<u32>>)) -> u64 {
+// A rule `before|after` broken by `after` appearing first
+#[derive(Debug, PartialEq)]
+struct Violation {
+    before: u32,
+    after: u32,
+}
+
+#[derive(Debug, PartialEq)]
+enum Validation {
+    Valid,
+    Reordered {
+        violations: Vec<Violation>,
+        order: Vec<u32>,
+    },
+    // The rules among the update's pages cannot all hold; `cycle` lists pages
+    // each required before the next, wrapping around
+    Cyclic {
+        violations: Vec<Violation>,
+        cycle: Vec<u32>,
+    },
+}
+
+fn violations(rules: &Graph<u32>, update: &[u32]) -> Vec<Violation> {
+    let ids: Vec<usize> = update.iter().map(|p| rules.id(p).unwrap()).collect();
+    let mut violations = Vec::new();
+    for (i, &earlier) in ids.iter().enumerate() {
+        for &later in &ids[i + 1..] {
+            if rules.has_edge(later, earlier) {
+                violations.push(Violation {
+                    before: *rules.name(later),
+                    after: *rules.name(earlier),
+                });
+            }
+        }
+    }
+    violations
+}
+
+// One cycle among vertices left over by a failed topological sort. Each of
+// them has a predecessor in the set, so walking backwards must repeat.
+fn find_cycle(rules: &Graph<u32>, stuck: &[usize]) -> Vec<usize> {
+    let mut walk = vec![stuck[0]];
+    loop {
+        let v = *walk.last().unwrap();
+        let prev = *rules
+            .predecessors(v)
+            .iter()
+            .find(|u| stuck.contains(u))
+            .unwrap();
+        if let Some(start) = walk.iter().position(|&w| w == prev) {
+            let mut cycle = walk.split_off(start);
+            cycle.reverse();
+            return cycle;
+        }
+        walk.push(prev);
+    }
+}
+
+// Check an update against the rules, explaining and fixing any problems
+fn validate(rules: &Graph<u32>, update: &[u32]) -> Validation {
+    let violations = violations(rules, update);
+    if violations.is_empty() {
+        return Validation::Valid;
+    }
+    let ids: Vec<usize> = update.iter().map(|p| rules.id(p).unwrap()).collect();
+    match rules.topological_sort(&ids) {
+        Ok(order) => Validation::Reordered {
+            violations,
+            order: order.iter().map(|&v| *rules.name(v)).collect(),
+        },
+        Err(stuck) => Validation::Cyclic {
+            violations,
+            cycle: find_cycle(rules, &stuck)
+                .iter()
+                .map(|&v| *rules.name(v))
+                .collect(),
+        },
+    }
+}
+
+fn part1(input: &(Graph<u32>, Vec<Vec<u32>>)) -> u64 {
     let (rules, updates) = input;
     updates
         .iter()
-        .filter(|u| {
-            // Find all valid updates
-            let mut seen = HashSet::new();
-            u.iter().all(|&num| {
-                if let Some(curr_rules) = rules.get(&num) {
-                    // If current number has a rule about previously seen number, return false
-                    if curr_rules.iter().any(|&rule| seen.contains(&rule)) {
-                        return false;
-                    }
-                }
-                seen.insert(num);
-                true
-            })
-        })
+        .filter(|u| validate(rules, u) == Validation::Valid)
         .map(|v| v[v.len() / 2] as u64)
         .sum::<u64>()
 }
 
-// Uses topological sort (DFS) from https://en.wikipedia.org/wiki/Topological_sorting#Depth-first_search
-fn part2(input: &(HashMap<u32, Vec<u32>>, Vec<Vec<u32>>)) -> u64 {
+fn part2(input: &(Graph<u32>, Vec<Vec<u32>>)) -> u64 {
     let (rules, updates) = input;
-
-    fn visit(
-        n: &u32,
-        rules: &HashMap<u32, Vec<u32>>,
-        visited: &mut HashSet<u32>,
-        remaining: &mut HashSet<u32>,
-        new: &mut Vec<u32>,
-    ) {
-        if !remaining.contains(&n) {
-            // "If n has a permanent mark"
-            return;
-        } else if visited.contains(&n) {
-            // "If n has a temporary mark"
-            return;
-        }
-        visited.insert(*n);
-        if let Some(curr_rules) = rules.get(n) {
-            curr_rules
-                .iter()
-                .for_each(|value| visit(value, rules, visited, remaining, new));
-        }
-        remaining.remove(&n);
-        new.push(*n);
-    }
-
     updates
         .iter()
-        .filter(|u| {
-            // Find all invalid updates
-            let mut seen = HashSet::new();
-            u.iter().any(|&num| {
-                if let Some(curr_rules) = rules.get(&num) {
-                    if curr_rules.iter().any(|&rule| seen.contains(&rule)) {
-                        return true;
-                    }
-                }
-                seen.insert(num);
-                false
-            })
+        .map(|u| match validate(rules, u) {
+            Validation::Valid => 0,
+            Validation::Reordered { order, .. } => order[order.len() / 2] as u64,
+            Validation::Cyclic { cycle, .. } => panic!("update {u:?} has cyclic rules {cycle:?}"),
         })
-        .map(|v| {
-            // Construct the new order using topological sort
-            // The order is reversed, but we only need the middle element :)
-            let mut new: Vec<u32> = vec![];
-            let mut remaining: HashSet<u32> = HashSet::from_iter(v.iter().cloned());
-            let mut visited: HashSet<u32> = HashSet::new();
-
-            while !remaining.is_empty() {
-                let n = *remaining.iter().next().unwrap();
-                visit(&n, rules, &mut visited, &mut remaining, &mut new);
-            }
-            new
-        })
-        .map(|v| v[v.len() / 2] as u64)
         .sum::<u64>()
 }
 
@@ -179,6 +211,79 @@ mod tests {
         assert_eq!(123, part2(&input));
     }
 
+    #[test]
+    fn test_validate() {
+        let (rules, updates) = _sample_input();
+        assert_eq!(Validation::Valid, validate(&rules, &updates[0]));
+        assert_eq!(
+            Validation::Reordered {
+                violations: vec![Violation {
+                    before: 97,
+                    after: 75
+                }],
+                order: vec![97, 75, 47, 61, 53],
+            },
+            validate(&rules, &updates[3])
+        );
+        let Validation::Reordered { violations, order } = validate(&rules, &updates[5]) else {
+            panic!("expected a reordering");
+        };
+        assert_eq!(vec![97, 75, 47, 29, 13], order);
+        assert_eq!(4, violations.len());
+        assert!(violations.contains(&Violation {
+            before: 29,
+            after: 13
+        }));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let input = process_input(
+            "1|2\n2|3\n3|1\n3|4\n\n4,3,2\n2,1,3,4\n1,2,4"
+                .split('\n')
+                .map(|s| s.to_string())
+                .collect(),
+        );
+        let (rules, updates) = &input;
+        assert_eq!(
+            Validation::Reordered {
+                violations: vec![
+                    Violation {
+                        before: 3,
+                        after: 4
+                    },
+                    Violation {
+                        before: 2,
+                        after: 3
+                    }
+                ],
+                order: vec![2, 3, 4],
+            },
+            validate(rules, &updates[0])
+        );
+        let Validation::Cyclic { violations, cycle } = validate(rules, &updates[1]) else {
+            panic!("expected a cycle");
+        };
+        assert_eq!(
+            vec![
+                Violation {
+                    before: 1,
+                    after: 2
+                },
+                Violation {
+                    before: 3,
+                    after: 1
+                }
+            ],
+            violations
+        );
+        // Same cycle regardless of where it starts
+        assert_eq!(3, cycle.len());
+        let start = cycle.iter().position(|&p| p == 1).unwrap();
+        assert_eq!(vec![1, 2, 3], [&cycle[start..], &cycle[..start]].concat());
+        assert_eq!(Validation::Valid, validate(rules, &updates[2]));
+    }
+
     #[test]
     fn real_part1() {
         let input = day_input();
@@ -1,6 +1,8 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, iter::Sum, ops::AddAssign};
 
-use aoc2024::common::read_input;
+use num_bigint::BigUint;
+
+use aoc2024::common::{arg_value, has_flag, read_input};
 
 const DAY: u32 = 11;
 
@@ -10,6 +12,17 @@ pub fn main() {
     println!("Day {DAY}, part 1: {part1_result}");
     let part2_result = part2(&input);
     println!("Day {DAY}, part 2: {part2_result}");
+
+    // Any number of blinks, counted without overflow
+    if let Some(blinks) = arg_value("--blinks") {
+        let (total, distinct) = evolve::<BigUint>(&input, blinks.parse().unwrap());
+        println!("Stones after {blinks} blinks: {total}");
+        if has_flag("--distinct") {
+            for (round, n) in distinct.iter().enumerate() {
+                println!("Blink {}: {n} distinct values", round + 1);
+            }
+        }
+    }
 }
 
 // Parse input
@@ -29,62 +42,105 @@ fn day_input() -> Vec<u64> {
     process_input(data)
 }
 
+// Halves of a number with an even count of digits, e.g. 1000 -> (10, 0)
+fn split_digits(value: u64) -> Option<(u64, u64)> {
+    let digits = value.checked_ilog10().map_or(1, |d| d + 1);
+    if digits.is_multiple_of(2) {
+        let half = 10u64.pow(digits / 2);
+        Some((value / half, value % half))
+    } else {
+        None
+    }
+}
+
 // Calculate new stone value(s) for a stone
-fn update_stone(value: u64) -> Vec<u64> {
+fn update_stone(value: u64) -> (u64, Option<u64>) {
     // Rule 0
     if value == 0 {
-        return vec![1];
+        return (1, None);
     }
 
     // Rule 1
-    let value_text = value.to_string();
-    if value_text.len() % 2 == 0 {
-        let splitted = value_text.split_at(value_text.len() / 2);
-        return vec![
-            splitted.0.parse::<u64>().unwrap(),
-            splitted.1.parse::<u64>().unwrap(),
-        ];
+    if let Some((left, right)) = split_digits(value) {
+        return (left, Some(right));
     }
 
-    vec![value * 2024]
+    (value * 2024, None)
 }
 
-// Combine stone counts
-fn combine_counts(input: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
-    let mut map = HashMap::new();
+// Number of stones for every distinct value. Stones with equal values evolve
+// alike, so only the counts grow; use u128 or BigUint depending on the blinks.
+struct StoneCounts<C> {
+    counts: HashMap<u64, C>,
+}
 
-    for (value, count) in input {
-        *map.entry(value).or_insert(0) += count;
+impl<C> StoneCounts<C>
+where
+    C: Clone + From<u8> + for<'a> AddAssign<&'a C> + for<'a> Sum<&'a C>,
+{
+    fn new(stones: &[u64]) -> StoneCounts<C> {
+        let mut counts: HashMap<u64, C> = HashMap::new();
+        for &stone in stones {
+            add_count(&mut counts, stone, &C::from(1));
+        }
+        StoneCounts { counts }
     }
 
-    map.into_iter().collect()
-}
+    fn blink(&mut self) {
+        let mut next: HashMap<u64, C> = HashMap::with_capacity(self.counts.len());
+        for (&value, count) in &self.counts {
+            let (left, right) = update_stone(value);
+            add_count(&mut next, left, count);
+            if let Some(right) = right {
+                add_count(&mut next, right, count);
+            }
+        }
+        self.counts = next;
+    }
 
-fn part1(input: &Vec<u64>) -> usize {
-    let mut current = input.clone();
-    for _ in 0..25 {
-        current = current.iter().map(|v| update_stone(*v)).flatten().collect();
+    fn total(&self) -> C {
+        self.counts.values().sum()
+    }
+
+    fn distinct(&self) -> usize {
+        self.counts.len()
     }
-    current.len()
 }
 
-fn part2(input: &Vec<u64>) -> u64 {
-    // Store stone values and their count in vector
-    let mut current: Vec<(u64, u64)> = input.iter().map(|v| (*v, 1)).collect();
-
-    for _ in 0..75 {
-        current = current
-            .iter()
-            .flat_map(|(value, count)| {
-                update_stone(*value)
-                    .iter()
-                    .map(|v| (*v, *count))
-                    .collect::<Vec<_>>()
-            })
-            .collect();
-        current = combine_counts(current);
+fn add_count<C: Clone + for<'a> AddAssign<&'a C>>(
+    counts: &mut HashMap<u64, C>,
+    value: u64,
+    count: &C,
+) {
+    match counts.get_mut(&value) {
+        Some(c) => *c += count,
+        None => {
+            counts.insert(value, count.clone());
+        }
     }
-    current.iter().map(|(_, count)| *count as u64).sum()
+}
+
+// Stones after the given number of blinks, with the distinct values after each
+fn evolve<C>(stones: &[u64], blinks: usize) -> (C, Vec<usize>)
+where
+    C: Clone + From<u8> + for<'a> AddAssign<&'a C> + for<'a> Sum<&'a C>,
+{
+    let mut counts: StoneCounts<C> = StoneCounts::new(stones);
+    let distinct = (0..blinks)
+        .map(|_| {
+            counts.blink();
+            counts.distinct()
+        })
+        .collect();
+    (counts.total(), distinct)
+}
+
+fn part1(input: &[u64]) -> u128 {
+    evolve::<u128>(input, 25).0
+}
+
+fn part2(input: &[u64]) -> u128 {
+    evolve::<u128>(input, 75).0
 }
 
 #[cfg(test)]
@@ -97,6 +153,39 @@ mod tests {
         assert_eq!(55312, part1(&input));
     }
 
+    #[test]
+    fn test_update_stone() {
+        assert_eq!(None, split_digits(0));
+        assert_eq!(None, split_digits(7));
+        assert_eq!(Some((1, 0)), split_digits(10));
+        assert_eq!(Some((10, 0)), split_digits(1000));
+        assert_eq!(Some((253, 0)), split_digits(253000));
+        assert_eq!(None, split_digits(99999));
+        assert_eq!(Some((12345678, 90123456)), split_digits(1234567890123456));
+        assert_eq!((1, None), update_stone(0));
+        assert_eq!((28676032, None), update_stone(14168));
+        assert_eq!((20, Some(24)), update_stone(2024));
+    }
+
+    #[test]
+    fn test_evolve() {
+        let input = _sample_input();
+        let (total, distinct) = evolve::<u128>(&input, 6);
+        assert_eq!(22, total);
+        assert_eq!(vec![3, 4, 5], distinct[..3].to_vec());
+        assert_eq!(0, evolve::<u128>(&[], 10).0);
+        assert_eq!(input.len() as u128, evolve::<u128>(&input, 0).0);
+
+        // Counts past u128 agree with u128 where both fit
+        let (small, _) = evolve::<u128>(&input, 75);
+        let (big, _) = evolve::<BigUint>(&input, 75);
+        assert_eq!(BigUint::from(small), big);
+        let (huge, distinct) = evolve::<BigUint>(&input, 1000);
+        assert!(huge.bits() > 128);
+        // The set of values stops growing
+        assert_eq!(distinct[900], distinct[999]);
+    }
+
     #[test]
     fn real_part1() {
         let input = day_input();
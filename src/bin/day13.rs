@@ -1,7 +1,4 @@
-use ndarray::prelude::*;
-use ndarray_linalg::Solve;
-
-use aoc2024::common::read_input;
+use aoc2024::common::{has_flag, read_input};
 use regex::Regex;
 
 const DAY: u32 = 13;
@@ -12,6 +9,14 @@ pub fn main() {
     println!("Day {DAY}, part 1: {part1_result}");
     let part2_result = part2(&input);
     println!("Day {DAY}, part 2: {part2_result}");
+
+    if has_flag("--details") {
+        for (i, machine) in input.iter().enumerate() {
+            let part1 = play_machine(machine, &PART1_CONFIG);
+            let part2 = play_machine(machine, &PART2_CONFIG);
+            println!("Machine {i}: part 1 {part1:?}, part 2 {part2:?}");
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -41,13 +46,13 @@ fn process_input(input: Vec<String>) -> Vec<Machine> {
     };
 
     for line in &input {
-        if let Some(caps) = button_a_regex.captures(&line) {
+        if let Some(caps) = button_a_regex.captures(line) {
             current.a_x = caps.get(1).unwrap().as_str().parse().unwrap();
             current.a_y = caps.get(2).unwrap().as_str().parse().unwrap();
-        } else if let Some(caps) = button_b_regex.captures(&line) {
+        } else if let Some(caps) = button_b_regex.captures(line) {
             current.b_x = caps.get(1).unwrap().as_str().parse().unwrap();
             current.b_y = caps.get(2).unwrap().as_str().parse().unwrap();
-        } else if let Some(caps) = prize_regex.captures(&line) {
+        } else if let Some(caps) = prize_regex.captures(line) {
             current.prize_x = caps.get(1).unwrap().as_str().parse().unwrap();
             current.prize_y = caps.get(2).unwrap().as_str().parse().unwrap();
             result.push(current);
@@ -92,36 +97,155 @@ fn day_input() -> Vec<Machine> {
     process_input(read_input(DAY))
 }
 
-fn machine_tokens(machine: &Machine, part2: bool) -> u64 {
-    let a: Array2<f64> = array![
-        [machine.a_x as f64, machine.b_x as f64],
-        [machine.a_y as f64, machine.b_y as f64]
-    ];
-    let part2_extra: u64 = 10000000000000;
-    let (target_x, target_y) = if part2 {
-        (machine.prize_x + part2_extra, machine.prize_y + part2_extra)
+#[derive(Clone, Copy, Debug)]
+struct ClawConfig {
+    cost_a: i128,
+    cost_b: i128,
+    offset: i128,              // added to both prize coordinates
+    press_limit: Option<i128>, // maximum presses per button
+}
+
+const PART1_CONFIG: ClawConfig = ClawConfig {
+    cost_a: 3,
+    cost_b: 1,
+    offset: 0,
+    press_limit: Some(100),
+};
+
+const PART2_CONFIG: ClawConfig = ClawConfig {
+    cost_a: 3,
+    cost_b: 1,
+    offset: 10000000000000,
+    press_limit: None,
+};
+
+#[derive(PartialEq, Debug)]
+enum Outcome {
+    Won { a: i128, b: i128, cost: i128 },
+    Unreachable,
+}
+
+// Extended Euclid: returns (g, s, t) with a*s + b*t = g = gcd(a, b)
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
     } else {
-        (machine.prize_x, machine.prize_y)
-    };
+        let (g, s, t) = extended_gcd(b, a % b);
+        (g, t, s - (a / b) * t)
+    }
+}
 
-    let b: Array1<f64> = array![target_x as f64, target_y as f64];
-    let x = a.solve_into(b).unwrap();
+fn ceil_div(a: i128, b: i128) -> i128 {
+    a.div_euclid(b) + (a.rem_euclid(b) != 0) as i128
+}
 
-    let result_x = (x[0].round() as u64) * machine.a_x + (x[1].round() as u64) * machine.b_x;
-    let result_y = (x[0].round() as u64) * machine.a_y + (x[1].round() as u64) * machine.b_y;
+// Cheapest non-negative (a, b) within the press limit with a * p + b * q = target,
+// for p, q >= 0
+fn cheapest_combination(
+    p: i128,
+    q: i128,
+    target: i128,
+    config: &ClawConfig,
+) -> Option<(i128, i128)> {
+    let limit = config.press_limit.unwrap_or(i128::MAX);
+    let cost = |&(a, b): &(i128, i128)| config.cost_a * a + config.cost_b * b;
+    if p == 0 && q == 0 {
+        return (target == 0).then_some((0, 0));
+    }
+    if p == 0 || q == 0 {
+        let step = p + q;
+        let presses = (target % step == 0 && target / step <= limit).then_some(target / step)?;
+        return Some(if p == 0 { (0, presses) } else { (presses, 0) });
+    }
+    let (g, s, t) = extended_gcd(p, q);
+    if target % g != 0 {
+        return None;
+    }
+    // All solutions: a = a0 + k * dq, b = b0 - k * dp
+    let (a0, b0) = (s * (target / g), t * (target / g));
+    let (dp, dq) = (p / g, q / g);
+    let mut k_min = ceil_div(-a0, dq);
+    let mut k_max = b0.div_euclid(dp);
+    if limit < i128::MAX {
+        k_min = k_min.max(ceil_div(b0 - limit, dp));
+        k_max = k_max.min((limit - a0).div_euclid(dq));
+    }
+    if k_min > k_max {
+        return None;
+    }
+    // Cost is linear in k, so one of the ends is the cheapest
+    [k_min, k_max]
+        .into_iter()
+        .map(|k| (a0 + k * dq, b0 - k * dp))
+        .min_by_key(cost)
+}
+
+// Presses of A and B reaching the prize, if possible
+fn solve_machine(machine: &Machine, config: &ClawConfig) -> Option<(i128, i128)> {
+    let (ax, ay) = (machine.a_x as i128, machine.a_y as i128);
+    let (bx, by) = (machine.b_x as i128, machine.b_y as i128);
+    let (px, py) = (
+        machine.prize_x as i128 + config.offset,
+        machine.prize_y as i128 + config.offset,
+    );
 
-    if result_x == target_x && result_y == target_y {
-        return 3 * x[0].round() as u64 + x[1].round() as u64;
+    let det = ax * by - ay * bx;
+    let (a, b) = if det != 0 {
+        // Cramer's rule, accepting only whole press counts
+        let a_num = px * by - py * bx;
+        let b_num = ax * py - ay * px;
+        if a_num % det != 0 || b_num % det != 0 {
+            return None;
+        }
+        (a_num / det, b_num / det)
+    } else {
+        // Buttons move along the same line, which must also contain the prize
+        if ax * py != ay * px || bx * py != by * px {
+            return None;
+        }
+        if ax != 0 || bx != 0 {
+            cheapest_combination(ax, bx, px, config)?
+        } else {
+            cheapest_combination(ay, by, py, config)?
+        }
+    };
+
+    let limit = config.press_limit.unwrap_or(i128::MAX);
+    let valid = (0..=limit).contains(&a)
+        && (0..=limit).contains(&b)
+        && a * ax + b * bx == px
+        && a * ay + b * by == py;
+    valid.then_some((a, b))
+}
+
+fn play_machine(machine: &Machine, config: &ClawConfig) -> Outcome {
+    match solve_machine(machine, config) {
+        Some((a, b)) => Outcome::Won {
+            a,
+            b,
+            cost: config.cost_a * a + config.cost_b * b,
+        },
+        None => Outcome::Unreachable,
     }
-    0
 }
 
-fn part1(input: &Vec<Machine>) -> u64 {
-    input.iter().map(|m| machine_tokens(m, false)).sum::<u64>()
+// Tokens needed to win every reachable prize
+fn total_tokens(input: &[Machine], config: &ClawConfig) -> i128 {
+    input
+        .iter()
+        .map(|m| match play_machine(m, config) {
+            Outcome::Won { cost, .. } => cost,
+            Outcome::Unreachable => 0,
+        })
+        .sum()
 }
 
-fn part2(input: &Vec<Machine>) -> u64 {
-    input.iter().map(|m| machine_tokens(m, true)).sum::<u64>()
+fn part1(input: &[Machine]) -> u64 {
+    total_tokens(input, &PART1_CONFIG) as u64
+}
+
+fn part2(input: &[Machine]) -> u64 {
+    total_tokens(input, &PART2_CONFIG) as u64
 }
 
 #[cfg(test)]
@@ -137,7 +261,107 @@ mod tests {
     #[test]
     fn test_part2() {
         let input = _sample_input();
-        println!("{:?}", part2(&input));
+        assert_eq!(875318608908, part2(&input));
+    }
+
+    fn machine(a: (u64, u64), b: (u64, u64), prize: (u64, u64)) -> Machine {
+        Machine {
+            a_x: a.0,
+            a_y: a.1,
+            b_x: b.0,
+            b_y: b.1,
+            prize_x: prize.0,
+            prize_y: prize.1,
+        }
+    }
+
+    #[test]
+    fn test_solve_machine() {
+        let input = _sample_input();
+        assert_eq!(Some((80, 40)), solve_machine(&input[0], &PART1_CONFIG));
+        assert_eq!(None, solve_machine(&input[1], &PART1_CONFIG));
+        assert_eq!(
+            Some((118679050709, 103199174542)),
+            solve_machine(&input[1], &PART2_CONFIG)
+        );
+
+        // Collinear buttons: the cheapest mix depends on distance per token
+        assert_eq!(
+            Some((0, 10)),
+            solve_machine(&machine((2, 2), (1, 1), (10, 10)), &PART1_CONFIG)
+        );
+        assert_eq!(
+            Some((3, 1)),
+            solve_machine(&machine((4, 8), (1, 2), (13, 26)), &PART1_CONFIG)
+        );
+        assert_eq!(
+            Some((1, 3)),
+            solve_machine(&machine((3, 3), (5, 5), (18, 18)), &PART1_CONFIG)
+        );
+        assert_eq!(
+            None,
+            solve_machine(&machine((4, 4), (6, 6), (9, 9)), &PART1_CONFIG)
+        );
+        assert_eq!(
+            None,
+            solve_machine(&machine((1, 1), (2, 2), (5, 6)), &PART1_CONFIG)
+        );
+        assert_eq!(
+            Some((0, 0)),
+            solve_machine(&machine((0, 0), (0, 0), (0, 0)), &PART1_CONFIG)
+        );
+        assert_eq!(
+            Some((0, 3)),
+            solve_machine(&machine((0, 0), (0, 4), (0, 12)), &PART1_CONFIG)
+        );
+    }
+
+    #[test]
+    fn test_claw_config() {
+        let input = _sample_input();
+        assert_eq!(
+            Outcome::Won {
+                a: 38,
+                b: 86,
+                cost: 200
+            },
+            play_machine(&input[2], &PART1_CONFIG)
+        );
+        assert_eq!(Outcome::Unreachable, play_machine(&input[3], &PART1_CONFIG));
+
+        // Press limit applies to both the unique and the collinear solutions
+        let limited = ClawConfig {
+            press_limit: Some(50),
+            ..PART1_CONFIG
+        };
+        assert_eq!(Outcome::Unreachable, play_machine(&input[0], &limited));
+        let collinear = machine((3, 3), (1, 1), (300, 300));
+        assert_eq!(
+            Outcome::Won {
+                a: 67,
+                b: 99,
+                cost: 300
+            },
+            play_machine(&collinear, &PART1_CONFIG)
+        );
+        assert_eq!(Outcome::Unreachable, play_machine(&collinear, &limited));
+
+        let expensive_a = ClawConfig {
+            cost_a: 5,
+            cost_b: 1,
+            offset: 0,
+            press_limit: None,
+        };
+        assert_eq!(
+            Outcome::Won {
+                a: 0,
+                b: 300,
+                cost: 300
+            },
+            play_machine(&collinear, &expensive_a)
+        );
+        // Machines 1 and 3 are unreachable; 0 and 2 cost 5 * 80 + 40 and 5 * 38 + 86
+        assert_eq!(716, total_tokens(&input, &expensive_a));
     }
 
     #[test]
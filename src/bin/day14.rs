@@ -1,4 +1,10 @@
-use aoc2024::common::read_input;
+use std::{
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use aoc2024::common::{arg_value, has_flag, read_input};
 use itertools::Itertools;
 use regex::Regex;
 
@@ -10,6 +16,39 @@ pub fn main() {
     println!("Day {DAY}, part 1: {part1_result}");
     let part2_result = part2(&input, false);
     println!("Day {DAY}, part 2: {part2_result}");
+
+    if has_flag("--render") {
+        let (width, height) = room_size(false);
+        for line in render(&input, width, height, part2_result) {
+            println!("{line}");
+        }
+    }
+
+    // Safety factor and region counts over time, e.g. `--partition 3x3 --frames 0..100`
+    if let Some(partition) = arg_value("--partition") {
+        let (cols, rows) = partition
+            .split_once('x')
+            .expect("Partition must be given as NxM");
+        let partition = (cols.parse().unwrap(), rows.parse().unwrap());
+        for time in frame_range().unwrap_or(100..101) {
+            let counts = region_counts(&input, room_size(false), time, partition);
+            let factor: usize = counts.iter().flatten().product();
+            println!("Time {time}: safety factor {factor}, regions {counts:?}");
+        }
+    }
+
+    // Export frames as images, e.g. `--export frames --frames 8000..8100 --format pgm`
+    if let Some(dir) = arg_value("--export") {
+        let (width, height) = room_size(false);
+        let times = frame_range().unwrap_or(part2_result..part2_result + 1);
+        let format = match arg_value("--format").as_deref() {
+            Some("pgm") => ImageFormat::Pgm,
+            _ => ImageFormat::Pbm,
+        };
+        let frames = export_frames(&input, width, height, times, Path::new(&dir), format)
+            .expect("Failed to export frames");
+        println!("Wrote {} frames to {dir}", frames.len());
+    }
 }
 
 struct Robot {
@@ -61,6 +100,15 @@ fn day_input() -> Vec<Robot> {
     process_input(read_input(DAY))
 }
 
+// Time range given as `--frames A..B`
+fn frame_range() -> Option<Range<i64>> {
+    let range = arg_value("--frames")?;
+    let (from, to) = range
+        .split_once("..")
+        .expect("Frames must be given as A..B");
+    Some(from.parse().unwrap()..to.parse().unwrap())
+}
+
 fn position_at_time(robot: &Robot, time: i64, width: i64, height: i64) -> (i64, i64) {
     (
         ((robot.x + time * robot.vx) % width + width) % width,
@@ -68,77 +116,176 @@ fn position_at_time(robot: &Robot, time: i64, width: i64, height: i64) -> (i64,
     )
 }
 
-// 1 | 2
-// -----
-// 3 | 4
-
-fn quadrant(pos: (i64, i64), width: i64, height: i64) -> i64 {
-    let (x, y) = pos;
-    let mid_x = width / 2;
-    let mid_y = height / 2;
-    if x == mid_x || y == mid_y {
-        // Middle
-        0
-    } else if x < mid_x && y < mid_y {
-        // Top-left
-        1
-    } else if x > mid_x && y < mid_y {
-        // Top-right
-        2
-    } else if x < mid_x && y > mid_y {
-        // Bottom-left
-        3
-    } else {
-        // Bottom-right
-        4
+// Split 0..size into equal parts. Tiles cut by a boundary (like the middle row
+// with an odd size) belong to no part.
+fn partition_index(value: i64, size: i64, parts: i64) -> Option<usize> {
+    let part = value * parts / size;
+    ((value + 1) * parts <= (part + 1) * size).then_some(part as usize)
+}
+
+// Robots in each region of a cols x rows partition of the room, indexed [row][col]
+fn region_counts(
+    input: &[Robot],
+    (width, height): (i64, i64),
+    time: i64,
+    (cols, rows): (i64, i64),
+) -> Vec<Vec<usize>> {
+    let mut counts = vec![vec![0; cols as usize]; rows as usize];
+    for r in input {
+        let (x, y) = position_at_time(r, time, width, height);
+        if let (Some(col), Some(row)) = (
+            partition_index(x, width, cols),
+            partition_index(y, height, rows),
+        ) {
+            counts[row][col] += 1;
+        }
     }
+    counts
+}
+
+// Product of the robot counts of all regions
+fn safety_factor(input: &[Robot], room: (i64, i64), time: i64, partition: (i64, i64)) -> usize {
+    region_counts(input, room, time, partition)
+        .iter()
+        .flatten()
+        .product()
 }
 
-fn draw(positions: Vec<(i64, i64)>, width: i64, height: i64, time: i64) -> bool {
-    let mut grid = vec![vec!['.'; width as usize]; height as usize];
-    for (x, y) in positions {
-        grid[y as usize][x as usize] = '#';
+// Room size for the sample and the real input
+fn room_size(test: bool) -> (i64, i64) {
+    if test {
+        (11, 7)
+    } else {
+        (101, 103)
     }
-    let possible = grid.iter().any(|row| {
-        let text: String = row.iter().collect();
-        text.contains("#######")
+}
+
+// Spread of coordinates, proportional to their variance
+fn spread(values: impl Iterator<Item = i64>) -> i64 {
+    let (n, sum, sum_sq) = values.fold((0, 0, 0), |(n, s, sq), v| (n + 1, s + v, sq + v * v));
+    n * sum_sq - sum * sum
+}
+
+// Time in 0..period when the given coordinate is most clustered
+fn tightest_time(input: &[Robot], period: i64, coordinate: impl Fn(&Robot, i64) -> i64) -> i64 {
+    (0..period)
+        .min_by_key(|&t| spread(input.iter().map(|r| coordinate(r, t))))
+        .unwrap()
+}
+
+// x and y repeat with periods width and height, so find the clustered time of both
+// separately and combine them with the Chinese remainder theorem
+fn find_easter_egg(input: &[Robot], width: i64, height: i64) -> Option<i64> {
+    let tx = tightest_time(input, width, |r, t| position_at_time(r, t, width, height).0);
+    let ty = tightest_time(input, height, |r, t| {
+        position_at_time(r, t, width, height).1
     });
-    if !possible {
-        return false;
-    }
-    println!("Time: {}", time);
-    for row in grid {
-        println!("{}", row.iter().join(""));
+    (0..height)
+        .map(|k| tx + k * width)
+        .find(|t| t % height == ty)
+}
+
+// Number of robots on each tile at the given time
+fn robot_counts(input: &[Robot], width: i64, height: i64, time: i64) -> Vec<Vec<u32>> {
+    let mut grid = vec![vec![0; width as usize]; height as usize];
+    for (x, y) in input
+        .iter()
+        .map(|r| position_at_time(r, time, width, height))
+    {
+        grid[y as usize][x as usize] += 1;
     }
-    true
+    grid
 }
 
-fn part1(input: &Vec<Robot>, test: bool) -> i64 {
-    let height = if test { 7 } else { 103 };
-    let width: i64 = if test { 11 } else { 101 };
-    input
+// Robot positions at the given time, drawn as text
+fn render(input: &[Robot], width: i64, height: i64, time: i64) -> Vec<String> {
+    robot_counts(input, width, height, time)
         .iter()
-        .map(|r| {
-            let pos = position_at_time(r, 100, width, height);
-            quadrant(pos, width, height)
-        })
-        .counts()
+        .map(|row| row.iter().map(|&c| if c > 0 { '#' } else { '.' }).join(""))
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ImageFormat {
+    Pbm, // black robots on white
+    Pgm, // brightness by number of robots on the tile
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Pbm => "pbm",
+            ImageFormat::Pgm => "pgm",
+        }
+    }
+}
+
+// Frame as a plain (ASCII) Netpbm image
+fn to_image(input: &[Robot], width: i64, height: i64, time: i64, format: ImageFormat) -> String {
+    let counts = robot_counts(input, width, height, time);
+    let (header, pixel): (String, Box<dyn Fn(u32) -> u32>) = match format {
+        ImageFormat::Pbm => (
+            format!("P1\n{width} {height}\n"),
+            Box::new(|c| (c > 0) as u32),
+        ),
+        ImageFormat::Pgm => {
+            let max = counts.iter().flatten().copied().max().unwrap_or(0).max(1);
+            (format!("P2\n{width} {height}\n{max}\n"), Box::new(|c| c))
+        }
+    };
+    let rows = counts
         .iter()
-        .filter(|(value, _)| **value != 0)
-        .map(|(_, count)| *count as i64)
-        .product()
+        .flat_map(|row| wrap_values(row.iter().map(|&c| pixel(c))))
+        .join("\n");
+    header + &rows + "\n"
 }
 
-fn part2(input: &Vec<Robot>, test: bool) -> i64 {
-    let height = if test { 7 } else { 103 };
-    let width: i64 = if test { 11 } else { 101 };
-    for i in 1..100000 {
-        let positions = input.iter().map(|r| position_at_time(r, i, width, height));
-        if draw(positions.collect(), width, height, i) {
-            return i;
+// Plain Netpbm lines should stay within 70 characters, so long rows are wrapped
+const IMAGE_LINE_LENGTH: usize = 70;
+
+fn wrap_values(values: impl Iterator<Item = u32>) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    for value in values {
+        let value = value.to_string();
+        let line = lines.last_mut().unwrap();
+        if line.is_empty() {
+            line.push_str(&value);
+        } else if line.len() + 1 + value.len() <= IMAGE_LINE_LENGTH {
+            line.push(' ');
+            line.push_str(&value);
+        } else {
+            lines.push(value);
         }
     }
-    -1
+    lines
+}
+
+// Write one image per time step into the directory, returning the file paths
+fn export_frames(
+    input: &[Robot],
+    width: i64,
+    height: i64,
+    times: Range<i64>,
+    dir: &Path,
+    format: ImageFormat,
+) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    times
+        .map(|time| {
+            let path = dir.join(format!("frame_{time:05}.{}", format.extension()));
+            fs::write(&path, to_image(input, width, height, time, format))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+fn part1(input: &[Robot], test: bool) -> i64 {
+    safety_factor(input, room_size(test), 100, (2, 2)) as i64
+}
+
+fn part2(input: &[Robot], test: bool) -> i64 {
+    let (width, height) = room_size(test);
+    find_easter_egg(input, width, height).unwrap_or(-1)
 }
 
 #[cfg(test)]
@@ -151,6 +298,114 @@ mod tests {
         assert_eq!(12, part1(&input, true));
     }
 
+    #[test]
+    fn test_find_easter_egg() {
+        // Robots gathered in a 5x5 square at time 1234, plus scattered ones
+        let (width, height) = room_size(false);
+        let time = 1234;
+        let mut seed: i64 = 7;
+        let mut random = |m: i64| {
+            seed = (seed * 1103515245 + 12345) % (1 << 31);
+            seed % m
+        };
+        let robots = (0..200)
+            .map(|i| {
+                let (vx, vy) = (random(201) - 100, random(201) - 100);
+                let (x, y) = if i < 100 {
+                    (40 + i % 5, 50 + i / 20)
+                } else {
+                    (random(width), random(height))
+                };
+                Robot {
+                    x: (x - time * vx).rem_euclid(width),
+                    y: (y - time * vy).rem_euclid(height),
+                    vx,
+                    vy,
+                }
+            })
+            .collect_vec();
+        assert_eq!(Some(time), find_easter_egg(&robots, width, height));
+        let frame = render(&robots, width, height, time);
+        assert_eq!(height as usize, frame.len());
+        assert_eq!("#####", &frame[50][40..45]);
+    }
+
+    #[test]
+    fn test_partitions() {
+        // Odd sizes leave the middle line out, even sizes split exactly
+        assert_eq!(Some(0), partition_index(4, 11, 2));
+        assert_eq!(None, partition_index(5, 11, 2));
+        assert_eq!(Some(1), partition_index(6, 11, 2));
+        assert_eq!(Some(0), partition_index(4, 10, 2));
+        assert_eq!(Some(1), partition_index(5, 10, 2));
+        assert_eq!(
+            [
+                Some(0),
+                Some(0),
+                Some(0),
+                None,
+                Some(1),
+                Some(1),
+                None,
+                Some(2),
+                Some(2),
+                Some(2)
+            ],
+            (0..10).map(|x| partition_index(x, 10, 3)).collect_vec()[..]
+        );
+
+        let input = _sample_input();
+        let room = room_size(true);
+        assert_eq!(
+            vec![vec![1, 3], vec![4, 1]],
+            region_counts(&input, room, 100, (2, 2))
+        );
+        assert_eq!(12, safety_factor(&input, room, 100, (2, 2)));
+        let counts = region_counts(&input, room, 0, (1, 1));
+        assert_eq!(vec![vec![12]], counts);
+    }
+
+    #[test]
+    fn test_images() {
+        let input = _sample_input();
+        let (width, height) = room_size(true);
+        let pbm = to_image(&input, width, height, 0, ImageFormat::Pbm);
+        let lines = pbm.lines().collect_vec();
+        assert_eq!(["P1", "11 7"], lines[..2]);
+        assert_eq!("1 0 1 1 0 0 0 0 0 0 0", lines[2]);
+        let pixels = |image: &str, header: usize| {
+            image
+                .lines()
+                .skip(header)
+                .flat_map(|l| l.split(' '))
+                .count()
+        };
+        assert_eq!((width * height) as usize, pixels(&pbm, 2));
+
+        let pgm = to_image(&input, width, height, 0, ImageFormat::Pgm);
+        let lines = pgm.lines().collect_vec();
+        assert_eq!(["P2", "11 7", "2"], lines[..3]);
+        assert_eq!("1 0 1 2 0 0 0 0 0 0 0", lines[3]);
+        assert_eq!((width * height) as usize, pixels(&pgm, 3));
+
+        // Rows of the real room are wrapped to at most 70 characters per line
+        let (width, height) = room_size(false);
+        let wide = to_image(&input, width, height, 0, ImageFormat::Pbm);
+        assert!(wide.lines().all(|l| l.len() <= 70));
+        assert_eq!((width * height) as usize, pixels(&wide, 2));
+        assert_eq!(2 + 3 * height as usize, wide.lines().count());
+
+        let dir = std::env::temp_dir().join(format!("aoc2024_day14_frames_{}", std::process::id()));
+        let frames = export_frames(&input, width, height, 3..6, &dir, ImageFormat::Pbm).unwrap();
+        assert_eq!(3, frames.len());
+        assert!(frames[2].ends_with("frame_00005.pbm"));
+        assert_eq!(
+            to_image(&input, width, height, 5, ImageFormat::Pbm),
+            fs::read_to_string(&frames[2]).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn real_part1() {
         let input = day_input();
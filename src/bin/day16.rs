@@ -1,112 +1,160 @@
 use std::{
-    cmp::min,
-    collections::{HashMap, HashSet},
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
 };
 
-use aoc2024::common::read_input;
+use aoc2024::common::{arg_value, has_flag, read_input};
 
 const DAY: u32 = 16;
 
 pub fn main() {
     let input = day_input();
-    let part1_result = part1(&input);
+    let costs = Costs {
+        step: arg_value("--step-cost").map_or(DEFAULT_COSTS.step, |c| c.parse().unwrap()),
+        turn: arg_value("--turn-cost").map_or(DEFAULT_COSTS.turn, |c| c.parse().unwrap()),
+    };
+    let part1_result = part1(&input, &costs);
     println!("Day {DAY}, part 1: {part1_result}");
-    let part2_result = part2(&input);
+    let part2_result = part2(&input, &costs);
     println!("Day {DAY}, part 2: {part2_result}");
-}
 
-struct Map {
-    start: usize,
-    target: (usize, usize),
-    vertices: Vec<((usize, usize), bool)>,
-    edges: Vec<(usize, usize, u64)>,
-    target_h: usize,
-    target_v: usize,
+    // Render either one best route or all tiles on best routes
+    if has_flag("--render") {
+        let solution = solve(&input, &costs);
+        let lines = if arg_value("--render").as_deref() == Some("tiles") {
+            render_tiles(&input, &best_tiles(&input, &solution))
+        } else {
+            render_path(&input, &best_path(&input, &solution))
+        };
+        for line in lines {
+            println!("{line}");
+        }
+    }
 }
 
-// Parse input
-fn process_input(input: Vec<String>) -> Map {
-    let mut start = (0, 0);
-    let mut target = (0, 0);
-    let mut vertices = Vec::new();
-    let mut edges: Vec<(usize, usize, u64)> = Vec::new();
-    let height = input.len();
-    let width = input[0].len();
-
-    // Create vertices
-    for (y, row) in input.iter().enumerate() {
-        for (x, c) in row.chars().enumerate() {
-            if c == 'S' {
-                // Start
-                start = (x, y);
-            } else if c == 'E' {
-                // End
-                target = (x, y);
-            }
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Direction {
+    East,
+    South,
+    West,
+    North,
+}
 
-            if c != '#' {
-                // Horizontal and vertical
-                vertices.push(((x, y), true)); // Horizontal
-                vertices.push(((x, y), false)); // Vertical
-            }
+const DIRECTIONS: [Direction; 4] = [
+    Direction::East,
+    Direction::South,
+    Direction::West,
+    Direction::North,
+];
+
+impl Direction {
+    fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::East => (1, 0),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
+            Direction::North => (0, -1),
         }
     }
 
-    // Create edges
-    for (i, &(a, horizontal)) in vertices.iter().enumerate() {
-        let (x, y) = a;
-        let h_weight = if horizontal { 1 } else { 1001 };
-        let v_weight = if horizontal { 1001 } else { 1 };
-        // Horizontal
-        if x > 0 {
-            if let Some(b) = vertices.iter().position(|&(v, h)| v == (x - 1, y) && h) {
-                edges.push((i, b, h_weight));
-            }
-        }
-        if x < width - 1 {
-            if let Some(b) = vertices.iter().position(|&(v, h)| v == (x + 1, y) && h) {
-                edges.push((i, b, h_weight));
-            }
+    fn arrow(self) -> char {
+        match self {
+            Direction::East => '>',
+            Direction::South => 'v',
+            Direction::West => '<',
+            Direction::North => '^',
         }
+    }
 
-        // Vertical
-        if y > 0 {
-            if let Some(b) = vertices.iter().position(|&(v, h)| v == (x, y - 1) && !h) {
-                edges.push((i, b, v_weight));
-            }
-        }
-        if y < height - 1 {
-            if let Some(b) = vertices.iter().position(|&(v, h)| v == (x, y + 1) && !h) {
-                edges.push((i, b, v_weight));
-            }
-        }
+    // Directions reachable with a single 90 degree turn
+    fn turns(self) -> [Direction; 2] {
+        let i = self as usize;
+        [DIRECTIONS[(i + 1) % 4], DIRECTIONS[(i + 3) % 4]]
+    }
+}
+
+struct Costs {
+    step: u64,
+    turn: u64,
+}
+
+const DEFAULT_COSTS: Costs = Costs {
+    step: 1,
+    turn: 1000,
+};
+
+// Search state: position and facing direction
+type State = ((usize, usize), Direction);
 
-        // Turn
-        if let Some(b) = vertices
+struct Map {
+    walls: Vec<Vec<bool>>,
+    width: usize,
+    height: usize,
+    start: (usize, usize),
+    target: (usize, usize),
+}
+
+impl Map {
+    fn index(&self, (pos, dir): State) -> usize {
+        (pos.1 * self.width + pos.0) * 4 + dir as usize
+    }
+
+    fn state(&self, index: usize) -> State {
+        let cell = index / 4;
+        (
+            (cell % self.width, cell / self.width),
+            DIRECTIONS[index % 4],
+        )
+    }
+
+    fn is_open(&self, (x, y): (usize, usize)) -> bool {
+        x < self.width && y < self.height && !self.walls[y][x]
+    }
+
+    // Moves from a state: step forward or turn in place
+    fn neighbors(&self, (pos, dir): State, costs: &Costs) -> Vec<(State, u64)> {
+        let mut next: Vec<(State, u64)> = dir
+            .turns()
             .iter()
-            .position(|&(v, h)| v == (x, y) && h == !horizontal)
-        {
-            edges.push((i, b, 1000));
+            .map(|&d| ((pos, d), costs.turn))
+            .collect();
+        let (dx, dy) = dir.delta();
+        let forward = (pos.0.wrapping_add_signed(dx), pos.1.wrapping_add_signed(dy));
+        if self.is_open(forward) {
+            next.push(((forward, dir), costs.step));
         }
+        next
     }
+}
 
-    let start_index = vertices.iter().position(|&(v, h)| v == start && h).unwrap();
-    let target_i1 = vertices
-        .iter()
-        .position(|&(v, h)| v == target && h)
-        .unwrap();
-    let target_i2 = vertices
+// Parse input
+fn process_input(input: Vec<String>) -> Map {
+    let mut start = (0, 0);
+    let mut target = (0, 0);
+    let walls = input
         .iter()
-        .position(|&(v, h)| v == target && !h)
-        .unwrap();
+        .enumerate()
+        .map(|(y, row)| {
+            row.chars()
+                .enumerate()
+                .map(|(x, c)| {
+                    if c == 'S' {
+                        start = (x, y);
+                    } else if c == 'E' {
+                        target = (x, y);
+                    }
+                    c == '#'
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
 
     Map {
-        start: start_index,
+        width: walls[0].len(),
+        height: walls.len(),
+        walls,
+        start,
         target,
-        vertices,
-        edges,
-        target_h: target_i1,
-        target_v: target_i2,
     }
 }
 
@@ -138,153 +186,129 @@ fn day_input() -> Map {
     process_input(read_input(DAY))
 }
 
-fn dijkstra(
-    start: usize,
-    target: (usize, usize),
-    vertices: &Vec<((usize, usize), bool)>,
-    edges: &Vec<(usize, usize, u64)>,
-    part2: bool,
-) -> (HashMap<usize, u64>, HashMap<usize, Vec<usize>>) {
-    let mut dist: HashMap<usize, u64> = vertices
-        .iter()
-        .enumerate()
-        .map(|(i, _)| (i, u64::MAX))
-        .collect();
-    let mut prev: HashMap<usize, Vec<usize>> = vertices
-        .iter()
-        .enumerate()
-        .map(|(i, _)| (i, Vec::new()))
-        .collect();
-    let mut queue = (0..vertices.len()).collect::<Vec<usize>>();
-    dist.insert(start, 0);
-
-    while queue.len() > 0 {
-        //println!("{} vertices left", queue.len());
-        let u = *queue.iter().min_by_key(|&i| dist[i]).unwrap();
-        if vertices[u].0 == target {
-            break;
+// Shortest distances to every state, with all equally good predecessors. With a
+// zero step or turn cost, ties can link states both ways, so `prev` may contain
+// cycles and anything walking it must guard against revisiting states.
+struct Solution {
+    dist: Vec<u64>,
+    prev: Vec<Vec<usize>>,
+}
+
+// Dijkstra over the implicit (cell, direction) graph, starting east from the start tile
+fn solve(map: &Map, costs: &Costs) -> Solution {
+    let states = map.width * map.height * 4;
+    let mut dist = vec![u64::MAX; states];
+    let mut prev: Vec<Vec<usize>> = vec![Vec::new(); states];
+    let mut queue = BinaryHeap::new();
+    let start = map.index((map.start, Direction::East));
+    dist[start] = 0;
+    queue.push(Reverse((0, start)));
+
+    while let Some(Reverse((d, u))) = queue.pop() {
+        if d > dist[u] {
+            continue;
         }
-        queue.retain(|&i| i != u);
-
-        for &(_, v, d) in edges.iter().filter(|(a, _, _)| *a == u) {
-            let alt = dist[&u] + d;
-            if alt < dist[&v] {
-                dist.insert(v, alt);
-                prev.insert(v, vec![u]);
-            } else if part2 && alt == dist[&v] {
-                // In part2, we need to keep track of all possible paths
-                prev.get_mut(&v).unwrap().push(u);
-                //println!("For vertex {:?}, prev is {:?}", vertices[v], prev[&v]);
+        for (next, cost) in map.neighbors(map.state(u), costs) {
+            let v = map.index(next);
+            let alt = d + cost;
+            if alt < dist[v] {
+                dist[v] = alt;
+                prev[v] = vec![u];
+                queue.push(Reverse((alt, v)));
+            } else if alt == dist[v] {
+                // Keep track of all possible paths
+                prev[v].push(u);
             }
         }
     }
-    return (dist, prev);
+    Solution { dist, prev }
 }
 
-#[allow(dead_code)]
-fn print_route(
-    start: usize,
-    target: (usize, usize),
-    vertices: &Vec<((usize, usize), bool)>,
-    prev: &HashMap<usize, i64>,
-) {
-    // Print route as grid, marking the start with S and end with E, path with #
-    let mut grid: Vec<Vec<char>> =
-        vec![
-            vec!['.'; vertices.iter().map(|v| v.0 .0).max().unwrap() + 1];
-            vertices.iter().map(|v| v.0 .1).max().unwrap() + 1
-        ];
-    let mut u = vertices
+// End states reached with the lowest score
+fn best_targets(map: &Map, solution: &Solution) -> Vec<usize> {
+    let best = best_cost(map, solution);
+    DIRECTIONS
         .iter()
-        .position(|&(v, h)| v == target && !h)
-        .unwrap();
-    while prev[&u] != -1 {
-        let (x, y) = vertices[u].0;
-        grid[y][x] = '#';
-        u = prev[&u] as usize;
-    }
-    let (sx, sy) = vertices[start].0;
-    let (ex, ey) = target;
-    grid[sy][sx] = 'S';
-    grid[ey][ex] = 'E';
-
-    for row in grid {
-        println!("{}", row.iter().collect::<String>());
-    }
+        .map(|&d| map.index((map.target, d)))
+        .filter(|&i| solution.dist[i] == best)
+        .collect()
 }
 
-// Slow but works
-fn part1(input: &Map) -> u64 {
-    let (dist, _) = dijkstra(
-        input.start,
-        input.target,
-        &input.vertices,
-        &input.edges,
-        false,
-    );
-    let d1 = dist[&input.target_h];
-    let d2 = dist[&input.target_v];
-    min(d1, d2)
+fn best_cost(map: &Map, solution: &Solution) -> u64 {
+    DIRECTIONS
+        .iter()
+        .map(|&d| solution.dist[map.index((map.target, d))])
+        .min()
+        .unwrap()
 }
 
-// Slow but works
-fn part2(input: &Map) -> i64 {
-    let (dist, prev) = dijkstra(
-        input.start,
-        input.target,
-        &input.vertices,
-        &input.edges,
-        true,
-    );
+// Tiles that are part of at least one best path
+fn best_tiles(map: &Map, solution: &Solution) -> HashSet<(usize, usize)> {
     let mut best: HashSet<(usize, usize)> = HashSet::new();
     let mut visited: HashSet<usize> = HashSet::new();
-    let d1 = dist[&input.target_h];
-    let d2 = dist[&input.target_v];
-    let mut queue = vec![if d1 < d2 {
-        input.target_h
-    } else {
-        input.target_v
-    }];
-
-    while queue.len() > 0 {
-        //println!("{} vertices left for best", queue.len());
-        let u = queue[0];
-        queue.swap_remove(0);
-        if visited.contains(&u) {
+    let mut queue = best_targets(map, solution);
+
+    while let Some(u) = queue.pop() {
+        if !visited.insert(u) {
             continue;
         }
+        best.insert(map.state(u).0);
+        queue.extend(solution.prev[u].iter());
+    }
+    best
+}
 
-        best.insert(input.vertices[u].0);
-
-        let mut next = prev[&u].clone();
-        queue.append(&mut next);
-        visited.insert(u);
+// One optimal route from the start to the target. The first predecessor of a
+// state was settled before it, so following those only loops at the start state,
+// which can gain zero-cost predecessors.
+fn best_path(map: &Map, solution: &Solution) -> Vec<State> {
+    let start = map.index((map.start, Direction::East));
+    let mut u = best_targets(map, solution)[0];
+    let mut path = vec![map.state(u)];
+    while u != start {
+        let p = solution.prev[u][0];
+        path.push(map.state(p));
+        u = p;
     }
+    path.reverse();
+    path
+}
 
-    /*// Print grid that shows all best spots as O
-    let mut grid: Vec<Vec<char>> =
-        vec![
-            vec!['.'; input.vertices.iter().map(|v| v.0 .0).max().unwrap() + 1];
-            input.vertices.iter().map(|v| v.0 .1).max().unwrap() + 1
-        ];
-    for &(x, y) in &best {
-        grid[y][x] = 'O';
+// Maze as text, with the start marked S and the end E
+fn render_grid(map: &Map, marks: impl IntoIterator<Item = ((usize, usize), char)>) -> Vec<String> {
+    let mut grid: Vec<Vec<char>> = map
+        .walls
+        .iter()
+        .map(|row| row.iter().map(|&w| if w { '#' } else { '.' }).collect())
+        .collect();
+    for ((x, y), c) in marks {
+        grid[y][x] = c;
     }
-    let (sx, sy) = input.vertices[input.start].0;
-    let (ex, ey) = input.target;
-    println!("Is end included: {}", best.contains(&input.target));
-    println!(
-        "Is start included: {}",
-        best.contains(&input.vertices[input.start].0)
-    );
+    let (sx, sy) = map.start;
+    let (ex, ey) = map.target;
     grid[sy][sx] = 'S';
     grid[ey][ex] = 'E';
+    grid.iter().map(|row| row.iter().collect()).collect()
+}
 
-    for row in grid {
-        println!("{}", row.iter().collect::<String>());
-    }*/
+// Route with arrows showing the facing direction on each tile
+fn render_path(map: &Map, path: &[State]) -> Vec<String> {
+    render_grid(map, path.iter().map(|&(pos, dir)| (pos, dir.arrow())))
+}
 
-    best.len() as i64
+// All tiles on some best route marked with O
+fn render_tiles(map: &Map, tiles: &HashSet<(usize, usize)>) -> Vec<String> {
+    render_grid(map, tiles.iter().map(|&pos| (pos, 'O')))
+}
+
+fn part1(input: &Map, costs: &Costs) -> u64 {
+    let solution = solve(input, costs);
+    best_cost(input, &solution)
+}
+
+fn part2(input: &Map, costs: &Costs) -> i64 {
+    let solution = solve(input, costs);
+    best_tiles(input, &solution).len() as i64
 }
 
 #[cfg(test)]
@@ -294,13 +318,68 @@ mod tests {
     #[test]
     fn test_part1() {
         let input = _sample_input();
-        assert_eq!(7036, part1(&input));
+        assert_eq!(7036, part1(&input, &DEFAULT_COSTS));
+    }
+
+    #[test]
+    fn test_costs() {
+        let input = process_input(
+            "#####\n#..E#\n#S###\n#####"
+                .split('\n')
+                .map(|s| s.to_string())
+                .collect(),
+        );
+        assert_eq!(2003, part1(&input, &DEFAULT_COSTS));
+        assert_eq!(3, part1(&input, &Costs { step: 1, turn: 0 }));
+        assert_eq!(32, part1(&input, &Costs { step: 10, turn: 1 }));
+        assert_eq!(4, part2(&input, &DEFAULT_COSTS));
+    }
+
+    #[test]
+    fn test_render() {
+        let input = process_input(
+            "#######\n#....E#\n#.#.###\n#S..#.#\n#######"
+                .split('\n')
+                .map(|s| s.to_string())
+                .collect(),
+        );
+        let solution = solve(&input, &DEFAULT_COSTS);
+        let path = best_path(&input, &solution);
+        assert_eq!(((1, 3), Direction::East), path[0]);
+        assert_eq!(input.target, path.last().unwrap().0);
+        assert_eq!(
+            ["#######", "#..>>E#", "#.#^###", "#S>^#.#", "#######"].to_vec(),
+            render_path(&input, &path)
+        );
+        assert_eq!(
+            ["#######", "#OOOOE#", "#O#O###", "#SOO#.#", "#######"].to_vec(),
+            render_tiles(&input, &best_tiles(&input, &solution))
+        );
+    }
+
+    #[test]
+    fn test_render_free_turns() {
+        let input = process_input(
+            "#####\n#..E#\n#S###\n#####"
+                .split('\n')
+                .map(|s| s.to_string())
+                .collect(),
+        );
+        let costs = Costs { step: 1, turn: 0 };
+        let solution = solve(&input, &costs);
+        let path = best_path(&input, &solution);
+        assert_eq!(((1, 2), Direction::East), path[0]);
+        assert_eq!(input.target, path.last().unwrap().0);
+        assert_eq!(
+            ["#####", "#>>E#", "#S###", "#####"].to_vec(),
+            render_path(&input, &path)
+        );
     }
 
     #[test]
     fn test_part2() {
         let input = _sample_input();
-        assert_eq!(45, part2(&input));
+        assert_eq!(45, part2(&input, &DEFAULT_COSTS));
     }
 
     #[test]
@@ -326,18 +405,18 @@ mod tests {
             .map(|s| s.to_string())
             .collect();
         let input = process_input(input);
-        assert_eq!(64, part2(&input));
+        assert_eq!(64, part2(&input, &DEFAULT_COSTS));
     }
 
     #[test]
     fn real_part1() {
         let input = day_input();
-        assert_eq!(107512, part1(&input));
+        assert_eq!(107512, part1(&input, &DEFAULT_COSTS));
     }
 
     #[test]
     fn real_part2() {
         let input = day_input();
-        assert_eq!(561, part2(&input));
+        assert_eq!(561, part2(&input, &DEFAULT_COSTS));
     }
 }
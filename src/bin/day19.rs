@@ -1,6 +1,4 @@
-use std::collections::HashMap;
-
-use aoc2024::common::read_input;
+use aoc2024::common::{arg_value, has_flag, read_input};
 
 const DAY: u32 = 19;
 
@@ -10,6 +8,48 @@ pub fn main() {
     println!("Day {DAY}, part 1: {part1_result}");
     let part2_result = part2(&input);
     println!("Day {DAY}, part 2: {part2_result}");
+
+    if has_flag("--fewest") {
+        let (towels, patterns) = &input;
+        let matcher = TowelMatcher::new(towels);
+        for pattern in patterns {
+            match matcher.fewest_towels(pattern) {
+                Some(n) => println!("{pattern}: {n} towels"),
+                None => println!("{pattern}: impossible"),
+            }
+        }
+    }
+
+    if has_flag("--redundant") {
+        let matcher = TowelMatcher::new(&input.0);
+        println!(
+            "Redundant towels: {}",
+            matcher.redundant_towels().join(", ")
+        );
+    }
+
+    // One arrangement for every design
+    if has_flag("--arrangement") {
+        let (towels, patterns) = &input;
+        let matcher = TowelMatcher::new(towels);
+        for pattern in patterns {
+            match matcher.arrangement(pattern) {
+                Some(arrangement) => println!("{pattern}: {}", arrangement.join(" ")),
+                None => println!("{pattern}: impossible"),
+            }
+        }
+    }
+
+    // List up to the given number of arrangements for every design
+    if let Some(limit) = arg_value("--arrangements") {
+        let (towels, patterns) = &input;
+        let matcher = TowelMatcher::new(towels);
+        for pattern in patterns {
+            for arrangement in matcher.arrangements(pattern, limit.parse().unwrap()) {
+                println!("{pattern}: {}", arrangement.join(" "));
+            }
+        }
+    }
 }
 
 // Parse input
@@ -42,101 +82,176 @@ fn day_input() -> (Vec<String>, Vec<String>) {
     process_input(read_input(DAY))
 }
 
-// My original implementation, for history purposes
-#[allow(dead_code)]
-fn is_valid_original(
-    pattern: &String,
-    towels: &Vec<String>,
-    memory: &mut HashMap<String, bool>,
-) -> bool {
-    //println!("Checking {pattern}");
-    towels.iter().any(|t| {
-        if t == pattern {
-            return true;
+#[derive(Default)]
+struct TrieNode {
+    children: Vec<(u8, usize)>,
+    towel: Option<usize>, // towel ending at this node
+}
+
+// Prefix tree of the towels, walked byte by byte along a design
+struct Trie {
+    nodes: Vec<TrieNode>,
+}
+
+impl Trie {
+    fn new(towels: &[String]) -> Trie {
+        let mut nodes = vec![TrieNode::default()];
+        for (i, towel) in towels.iter().enumerate() {
+            let mut node = 0;
+            for &b in towel.as_bytes() {
+                node = match nodes[node].children.iter().find(|&&(c, _)| c == b) {
+                    Some(&(_, child)) => child,
+                    None => {
+                        nodes.push(TrieNode::default());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.push((b, child));
+                        child
+                    }
+                };
+            }
+            nodes[node].towel = Some(i);
         }
-        if memory.contains_key(pattern) {
-            //println!("Memory hit for {pattern}, result is {}", memory[pattern]);
-            return memory[pattern];
+        Trie { nodes }
+    }
+
+    // Towels matching the design at `start`, as (end position, towel index)
+    fn matches<'a>(
+        &'a self,
+        design: &'a [u8],
+        start: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let mut node = Some(0);
+        (start..design.len())
+            .map_while(move |pos| {
+                let current = node?;
+                node = self.nodes[current]
+                    .children
+                    .iter()
+                    .find(|&&(c, _)| c == design[pos])
+                    .map(|&(_, child)| child);
+                node.map(|n| (pos + 1, self.nodes[n].towel))
+            })
+            .filter_map(|(end, towel)| towel.map(|t| (end, t)))
+    }
+}
+
+struct TowelMatcher<'a> {
+    towels: &'a [String],
+    trie: Trie,
+}
+
+impl<'a> TowelMatcher<'a> {
+    fn new(towels: &'a [String]) -> TowelMatcher<'a> {
+        TowelMatcher {
+            towels,
+            trie: Trie::new(towels),
         }
+    }
 
-        if pattern.find(t).unwrap_or(usize::MAX) == 0 {
-            // Pattern starts with towel t
-            let remaining = pattern[t.len()..].to_string();
-            let status = is_valid_original(&remaining, towels, memory);
-            //println!("Memory miss for {remaining}, result is {}", status);
-            memory.insert(remaining, status);
-            return status;
+    // Arrangements of every suffix of the design, the whole design at index 0
+    fn suffix_ways(&self, design: &str) -> Vec<u128> {
+        let bytes = design.as_bytes();
+        let mut ways = vec![0u128; bytes.len() + 1];
+        ways[bytes.len()] = 1;
+        for start in (0..bytes.len()).rev() {
+            ways[start] = self
+                .trie
+                .matches(bytes, start)
+                .map(|(end, _)| ways[end])
+                .sum();
         }
-        false
-    })
-}
+        ways
+    }
 
-// Pseudocode from https://stackoverflow.com/a/5996945
-fn is_valid(pattern: &String, towels: &Vec<String>, memory: &mut HashMap<String, bool>) -> bool {
-    // Base cases
-    if pattern.len() == 0 {
-        return true;
+    fn count(&self, design: &str) -> u128 {
+        self.suffix_ways(design)[0]
     }
-    if memory.contains_key(pattern) {
-        return memory[pattern];
+
+    fn is_possible(&self, design: &str) -> bool {
+        self.count(design) > 0
     }
 
-    memory.insert(pattern.clone(), false);
-    for t in towels {
-        let len = t.len();
-        if len > pattern.len() {
-            continue;
-        }
-        let start = pattern[..len].to_string();
-        let rest = pattern[len..].to_string();
-        if start == *t && is_valid(&rest, towels, memory) {
-            memory.insert(pattern.clone(), true);
+    // Smallest number of towels forming the design, if any arrangement exists
+    fn fewest_towels(&self, design: &str) -> Option<usize> {
+        let bytes = design.as_bytes();
+        let mut fewest: Vec<Option<usize>> = vec![None; bytes.len() + 1];
+        fewest[bytes.len()] = Some(0);
+        for start in (0..bytes.len()).rev() {
+            fewest[start] = self
+                .trie
+                .matches(bytes, start)
+                .filter_map(|(end, _)| fewest[end].map(|n| n + 1))
+                .min();
         }
+        fewest[0]
     }
 
-    memory[pattern]
-}
+    // Towels that other towels can also form. The towel itself accounts for
+    // exactly one arrangement; any other one only uses shorter towels.
+    fn redundant_towels(&self) -> Vec<&'a str> {
+        self.towels
+            .iter()
+            .filter(|t| self.count(t) > 1)
+            .map(|t| t.as_str())
+            .collect()
+    }
 
-fn calculate_ways(
-    pattern: &String,
-    towels: &Vec<String>,
-    memory: &mut HashMap<String, u64>,
-) -> u64 {
-    if memory.contains_key(pattern) {
-        return memory[pattern];
+    // Some arrangement of the design, if any exists
+    fn arrangement(&self, design: &str) -> Option<Vec<&'a str>> {
+        self.arrangements(design, 1).pop()
     }
-    if pattern.len() == 0 {
-        return 1;
+
+    // Up to `limit` arrangements, following only suffixes that can be completed
+    fn arrangements(&self, design: &str, limit: usize) -> Vec<Vec<&'a str>> {
+        let ways = self.suffix_ways(design);
+        let mut found = Vec::new();
+        self.collect_arrangements(
+            design.as_bytes(),
+            0,
+            &ways,
+            &mut Vec::new(),
+            limit,
+            &mut found,
+        );
+        found
     }
-    let mut ways = 0;
 
-    // Recursive case
-    for i in towels {
-        if pattern.starts_with(i) {
-            let remaining = &pattern[i.len()..].to_string();
-            ways += calculate_ways(remaining, towels, memory);
+    fn collect_arrangements(
+        &self,
+        design: &[u8],
+        start: usize,
+        ways: &[u128],
+        current: &mut Vec<&'a str>,
+        limit: usize,
+        found: &mut Vec<Vec<&'a str>>,
+    ) {
+        if found.len() >= limit {
+            return;
+        }
+        if start == design.len() {
+            found.push(current.clone());
+            return;
+        }
+        for (end, towel) in self.trie.matches(design, start) {
+            if ways[end] > 0 {
+                current.push(&self.towels[towel]);
+                self.collect_arrangements(design, end, ways, current, limit, found);
+                current.pop();
+            }
         }
     }
-    memory.insert(pattern.clone(), ways);
-    ways
 }
 
 fn part1(input: &(Vec<String>, Vec<String>)) -> i64 {
     let (towels, patterns) = input;
-    let mut memory = HashMap::new();
-    patterns
-        .iter()
-        .filter(|p| is_valid(p, towels, &mut memory))
-        .count() as i64
+    let matcher = TowelMatcher::new(towels);
+    patterns.iter().filter(|p| matcher.is_possible(p)).count() as i64
 }
 
-fn part2(input: &(Vec<String>, Vec<String>)) -> u64 {
+fn part2(input: &(Vec<String>, Vec<String>)) -> u128 {
     let (towels, patterns) = input;
-    let mut memory = HashMap::new();
-    patterns
-        .iter()
-        .map(|p| calculate_ways(p, towels, &mut memory))
-        .sum()
+    let matcher = TowelMatcher::new(towels);
+    patterns.iter().map(|p| matcher.count(p)).sum()
 }
 
 #[cfg(test)]
@@ -155,6 +270,66 @@ mod tests {
         assert_eq!(16, part2(&input));
     }
 
+    #[test]
+    fn test_matcher() {
+        let (towels, _) = _sample_input();
+        let matcher = TowelMatcher::new(&towels);
+        assert_eq!(2, matcher.count("brwrr"));
+        assert_eq!(6, matcher.count("rrbgbr"));
+        assert_eq!(0, matcher.count("ubwu"));
+        assert_eq!(1, matcher.count(""));
+        assert_eq!(vec![4, 2, 2, 1, 1], matcher.suffix_ways("gbbr"));
+
+        let mut all = matcher.arrangements("brwrr", 10);
+        all.sort();
+        assert_eq!(vec![vec!["b", "r", "wr", "r"], vec!["br", "wr", "r"]], all);
+        assert_eq!(1, matcher.arrangements("rrbgbr", 1).len());
+        assert_eq!(None, matcher.arrangement("bbrgwb"));
+        let one = matcher.arrangement("bwurrg").unwrap();
+        assert_eq!("bwurrg", one.concat());
+    }
+
+    #[test]
+    fn test_queries() {
+        let (towels, patterns) = _sample_input();
+        let matcher = TowelMatcher::new(&towels);
+        assert_eq!(
+            vec![true, true, true, true, false, true, true, false],
+            patterns
+                .iter()
+                .map(|p| matcher.is_possible(p))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                Some(3),
+                Some(4),
+                Some(2),
+                Some(4),
+                None,
+                Some(4),
+                Some(3),
+                None
+            ],
+            patterns
+                .iter()
+                .map(|p| matcher.fewest_towels(p))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(0), matcher.fewest_towels(""));
+        assert_eq!(vec!["rb", "gb", "br"], matcher.redundant_towels());
+    }
+
+    #[test]
+    fn test_large_counts() {
+        // Counts beyond u64: every split of a 100 byte design into a and aa
+        let towels = vec!["a".to_string(), "aa".to_string()];
+        let matcher = TowelMatcher::new(&towels);
+        let fib = (0..100).fold((1u128, 1u128), |(a, b), _| (b, a + b)).0;
+        assert_eq!(fib, matcher.count(&"a".repeat(100)));
+        assert!(fib > u64::MAX as u128);
+    }
+
     #[test]
     fn real_part1() {
         let input = day_input();
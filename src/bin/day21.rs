@@ -1,7 +1,9 @@
-use std::{collections::HashMap, vec};
+use std::collections::{HashMap, HashSet};
 
-use aoc2024::common::read_input;
+use aoc2024::common::{arg_value, read_input};
 use itertools::Itertools;
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
 
 const DAY: u32 = 21;
 
@@ -11,6 +13,24 @@ pub fn main() {
     println!("Day {DAY}, part 1: {part1_result}");
     let part2_result = part2(&input);
     println!("Day {DAY}, part 2: {part2_result}");
+
+    // Per-code table for any number of robots, e.g. `--robots 200`
+    if let Some(robots) = arg_value("--robots") {
+        let rows = breakdown(&input, robots.parse().expect("Invalid robot count"));
+        print_breakdown(&rows);
+        let total: BigUint = rows.into_iter().map(|row| row.complexity).sum();
+        println!("Total: {total}");
+    }
+
+    // Print an optimal sequence for each code, e.g. `--sequences 2`
+    if let Some(depth) = arg_value("--sequences") {
+        let mut chain = KeypadChain::door(depth.parse().expect("Invalid depth"));
+        for code in &input {
+            let sequence = chain.expand(0, code);
+            assert_eq!(Ok(code.clone()), chain.replay(&sequence));
+            println!("{code}: {sequence}");
+        }
+    }
 }
 
 // Parse input
@@ -18,222 +38,205 @@ fn process_input(input: Vec<String>) -> Vec<String> {
     input
 }
 
-fn possible_paths() -> HashMap<(char, char), Vec<String>> {
-    let others = "123456789".chars().collect::<Vec<char>>();
-    let mut paths: HashMap<(char, char), Vec<String>> = HashMap::new();
-    for i in others.iter() {
-        for j in others.iter() {
-            let start_col = (i.to_digit(10).unwrap() + 2) % 3;
-            let start_row = (i.to_digit(10).unwrap() - 1) / 3;
-            let end_col = (j.to_digit(10).unwrap() + 2) % 3;
-            let end_row = (j.to_digit(10).unwrap() - 1) / 3;
-            let sideways = if start_col > end_col {
-                "<".repeat((start_col - end_col).try_into().unwrap())
-            } else {
-                ">".repeat((end_col - start_col).try_into().unwrap())
-            };
-            let verticals = if start_row > end_row {
-                "v".repeat((start_row - end_row).try_into().unwrap())
-            } else {
-                "^".repeat((end_row - start_row).try_into().unwrap())
-            };
-            let path = format!("{}{}", sideways, verticals);
-            paths.insert(
-                (*i, *j),
-                path.chars()
-                    .permutations(path.len())
-                    .unique()
-                    .map(|a| format!("{}A", a.iter().collect::<String>()))
-                    .collect(),
-            );
-        }
-    }
-    // 0 is always accessed from A or 2
-    for j in others.iter() {
-        let from_2 = paths.get(&(*j, '2')).unwrap().iter().map(|s| {
-            let mut s1 = s.clone();
-            s1.pop();
-            format!("{}vA", s1)
-        });
-        let from_a = paths.get(&(*j, '3')).unwrap().iter().map(|s| {
-            let mut s1 = s.clone();
-            s1.pop();
-            format!("{}v<A", s1)
-        });
-        let combined: Vec<String> = from_2.chain(from_a).unique().collect();
-        paths.insert((*j, '0'), combined);
-    }
-    paths.insert(('A', '0'), vec!["<A".to_string()]);
-    for j in others.iter() {
-        let from_2 = paths
-            .get(&('2', *j))
-            .unwrap()
-            .iter()
-            .map(|s| format!("^{s}"));
-        let from_a = paths
-            .get(&('3', *j))
-            .unwrap()
-            .iter()
-            .map(|s| format!(">^{s}"));
-        let combined: Vec<String> = from_2.chain(from_a).unique().collect();
-        paths.insert(('0', *j), combined);
-    }
+const NUMERIC_LAYOUT: [&str; 4] = ["789", "456", "123", " 0A"];
+const DIRECTIONAL_LAYOUT: [&str; 2] = [" ^A", "<v>"];
+
+// Grid of keys, where spaces are gaps the robot arm must never point at
+struct Keypad {
+    keys: HashMap<char, (i32, i32)>,
+    paths: HashMap<(char, char), Vec<String>>,
+}
 
-    // A is always accessed from 0 or A
-    for j in others.iter() {
-        let from_0 = paths.get(&(*j, '0')).unwrap().iter().map(|s| {
-            let mut s1 = s.clone();
-            s1.pop();
-            format!("{}>A", s1)
-        });
-        let from_3 = paths.get(&(*j, '3')).unwrap().iter().map(|s| {
-            let mut s1 = s.clone();
-            s1.pop();
-            format!("{}vA", s1)
-        });
-        let combined: Vec<String> = from_0.chain(from_3).unique().collect();
-        paths.insert((*j, 'A'), combined);
+impl Keypad {
+    fn key_at(&self, pos: (i32, i32)) -> Option<char> {
+        self.keys.iter().find(|(_, &p)| p == pos).map(|(&c, _)| c)
     }
-    paths.insert(('0', 'A'), vec![">A".to_string()]);
-    for j in others.iter() {
-        let from_0 = paths
-            .get(&('0', *j))
-            .unwrap()
-            .iter()
-            .map(|s| format!("<{s}"));
-        let from_3 = paths
-            .get(&('3', *j))
-            .unwrap()
+
+    fn from_layout(rows: &[&str]) -> Keypad {
+        let keys: HashMap<char, (i32, i32)> = rows
             .iter()
-            .map(|s| format!("^{s}"));
-        let combined: Vec<String> = from_0.chain(from_3).unique().collect();
-        paths.insert(('A', *j), combined);
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c != ' ')
+                    .map(move |(x, c)| (c, (x as i32, y as i32)))
+            })
+            .collect();
+        let paths = keys
+            .keys()
+            .cartesian_product(keys.keys())
+            .map(|(&from, &to)| ((from, to), shortest_paths(&keys, from, to)))
+            .collect();
+        Keypad { keys, paths }
     }
+}
+
+// All shortest move sequences from one key to another (followed by A)
+// that stay on the keys. Only the interleavings of the horizontal and vertical
+// moves are generated, pruning any prefix that crosses a gap.
+fn shortest_paths(keys: &HashMap<char, (i32, i32)>, from: char, to: char) -> Vec<String> {
+    let (x0, y0) = keys[&from];
+    let (x1, y1) = keys[&to];
+    let horizontal = (if x1 > x0 { '>' } else { '<' }, (x1 - x0).signum(), 0);
+    let vertical = (if y1 > y0 { 'v' } else { '^' }, 0, (y1 - y0).signum());
+    let positions: HashSet<(i32, i32)> = keys.values().copied().collect();
+    let mut paths = Vec::new();
+    interleave_moves(
+        &positions,
+        (x0, y0),
+        [
+            (horizontal, (x1 - x0).unsigned_abs()),
+            (vertical, (y1 - y0).unsigned_abs()),
+        ],
+        &mut String::new(),
+        &mut paths,
+    );
+    paths
+}
 
-    // Filter out too long paths
-    for i in "0123456789A".chars() {
-        paths.insert((i, i), vec!["A".to_string()]);
-        for j in "0123456789A".chars() {
-            let start_col = if i == 'A' {
-                2
-            } else if i == '0' {
-                1
-            } else {
-                (i.to_digit(10).unwrap() + 2) % 3
-            };
-            let start_row = if i == 'A' || i == '0' {
-                0
-            } else {
-                (i.to_digit(10).unwrap() - 1) / 3 + 1
-            };
-            let end_col = if j == 'A' {
-                2
-            } else if j == '0' {
-                1
-            } else {
-                (j.to_digit(10).unwrap() + 2) % 3
-            };
-            let end_row = if j == 'A' || j == '0' {
-                0
-            } else {
-                (j.to_digit(10).unwrap() - 1) / 3 + 1
-            };
-            let steps = (start_col as i32 - end_col as i32).abs()
-                + (start_row as i32 - end_row as i32).abs()
-                + 1;
-            let entry = paths.get_mut(&(i, j)).unwrap();
-            entry.retain(|p| p.len() as i32 == steps);
+// Depth-first over the remaining (horizontal, vertical) move counts
+fn interleave_moves(
+    positions: &HashSet<(i32, i32)>,
+    (x, y): (i32, i32),
+    remaining: [((char, i32, i32), u32); 2],
+    path: &mut String,
+    paths: &mut Vec<String>,
+) {
+    if remaining.iter().all(|&(_, n)| n == 0) {
+        paths.push(format!("{path}A"));
+        return;
+    }
+    for i in 0..2 {
+        let ((arrow, dx, dy), n) = remaining[i];
+        let next = (x + dx, y + dy);
+        if n == 0 || !positions.contains(&next) {
+            continue;
         }
+        let mut rest = remaining;
+        rest[i].1 -= 1;
+        path.push(arrow);
+        interleave_moves(positions, next, rest, path, paths);
+        path.pop();
     }
-
-    paths
 }
 
-fn possible_paths2() -> HashMap<(char, char), Vec<String>> {
-    let paths: HashMap<(char, char), Vec<String>> = HashMap::from([
-        // A
-        (('A', 'A'), vec!["A".to_string()]),
-        (('A', '^'), vec!["<A".to_string()]),
-        (('A', '>'), vec!["vA".to_string()]),
-        (('A', 'v'), vec!["v<A".to_string(), "<vA".to_string()]),
-        (('A', '<'), vec!["v<<A".to_string(), "<v<A".to_string()]),
-        // ^
-        (('^', 'A'), vec![">A".to_string()]),
-        (('^', '^'), vec!["A".to_string()]),
-        (('^', '>'), vec![">vA".to_string(), "v>A".to_string()]),
-        (('^', 'v'), vec!["vA".to_string()]),
-        (('^', '<'), vec!["v<A".to_string()]),
-        // <
-        (('<', 'A'), vec![">>^A".to_string(), ">^>A".to_string()]),
-        (('<', '^'), vec![">^A".to_string()]),
-        (('<', '>'), vec![">>A".to_string()]),
-        (('<', 'v'), vec![">A".to_string()]),
-        (('<', '<'), vec!["A".to_string()]),
-        // v
-        (('v', 'A'), vec!["^>A".to_string(), ">^A".to_string()]),
-        (('v', '^'), vec!["^A".to_string()]),
-        (('v', '>'), vec![">A".to_string()]),
-        (('v', 'v'), vec!["A".to_string()]),
-        (('v', '<'), vec!["<A".to_string()]),
-        // <
-        (('>', 'A'), vec!["^A".to_string()]),
-        (('>', '^'), vec!["<^A".to_string(), "^<A".to_string()]),
-        (('>', '>'), vec!["A".to_string()]),
-        (('>', 'v'), vec!["<A".to_string()]),
-        (('>', '<'), vec!["<<A".to_string()]),
-    ]);
-    paths
+// Chain of keypads: the code is typed on the first one, each following keypad
+// controls the robot at the previous one, and the last one is used by a human
+struct KeypadChain {
+    keypads: Vec<Keypad>,
+    memo: HashMap<(usize, char, char), BigUint>,
 }
 
-fn required_keypresses(
-    current: char,
-    next: char,
-    paths2: &HashMap<(char, char), Vec<String>>,
-    remaining: usize,
-    memo: &mut HashMap<(char, char, usize), u64>,
-) -> u64 {
-    // Memoization
-    //println!("{current} -> {next}, remaining: {remaining}");
-    if let Some(&res) = memo.get(&(current, next, remaining)) {
-        return res;
+impl KeypadChain {
+    fn new(keypads: Vec<Keypad>) -> KeypadChain {
+        // Keypads controlling a robot need the arrow keys and A
+        let controls = keypads
+            .iter()
+            .skip(1)
+            .all(|k| "<>^vA".chars().all(|c| k.keys.contains_key(&c)));
+        assert!(controls, "Controlling keypads must contain <>^vA");
+        KeypadChain {
+            keypads,
+            memo: HashMap::new(),
+        }
+    }
+
+    // Door keypad followed by the given number of robot-operated directional keypads
+    // and the human's directional keypad
+    fn door(directional_robots: usize) -> KeypadChain {
+        let keypads = std::iter::once(Keypad::from_layout(&NUMERIC_LAYOUT))
+            .chain((0..=directional_robots).map(|_| Keypad::from_layout(&DIRECTIONAL_LAYOUT)))
+            .collect();
+        KeypadChain::new(keypads)
     }
-    let res = if remaining == 0 {
-        let result = paths2
-            .get(&(current, next))
-            .unwrap()
+
+    // Human keypresses needed to move from one key to another on a level and press it
+    fn press_cost(&mut self, level: usize, from: char, to: char) -> BigUint {
+        if level == self.keypads.len() - 1 {
+            return BigUint::one();
+        }
+        if let Some(res) = self.memo.get(&(level, from, to)) {
+            return res.clone();
+        }
+        let paths = self.keypads[level].paths[&(from, to)].clone();
+        let res = paths
             .iter()
-            .map(|s| s.len())
+            .map(|p| self.sequence_cost(level + 1, p))
             .min()
-            .unwrap()
-            .try_into()
             .unwrap();
-        //println!("{current} -> {next}: {result}");
-        result
-    } else {
-        let result = paths2
-            .get(&(current, next))
-            .unwrap()
-            .iter()
-            .map(|s| {
-                s.chars()
-                    .into_iter()
-                    .fold(('A', 0), |(prev, acc), c| {
-                        (
-                            c,
-                            acc + required_keypresses(prev, c, paths2, remaining - 1, memo),
-                        )
-                    })
-                    .1
+        self.memo.insert((level, from, to), res.clone());
+        res
+    }
+
+    // Human keypresses needed to type a sequence on a level, starting from A
+    fn sequence_cost(&mut self, level: usize, sequence: &str) -> BigUint {
+        sequence
+            .chars()
+            .fold(('A', BigUint::zero()), |(prev, acc), c| {
+                (c, acc + self.press_cost(level, prev, c))
             })
-            .min()
-            .unwrap();
-        //println!("{current} -> {next}: {result}, remaining: {remaining}");
+            .1
+    }
+
+    // One optimal sequence of human keypresses typing the sequence on a level.
+    // The length grows exponentially with the depth of the chain.
+    fn expand(&mut self, level: usize, sequence: &str) -> String {
+        if level == self.keypads.len() - 1 {
+            return sequence.to_string();
+        }
+        let mut prev = 'A';
+        let mut result = String::new();
+        for c in sequence.chars() {
+            let paths = self.keypads[level].paths[&(prev, c)].clone();
+            let best = paths
+                .iter()
+                .min_by_key(|p| self.sequence_cost(level + 1, p))
+                .unwrap();
+            result += &self.expand(level + 1, best);
+            prev = c;
+        }
         result
-    };
+    }
 
-    memo.insert((current, next, remaining), res);
-    res
+    // Replay human keypresses through the chain, returning what gets typed on the
+    // first keypad or an error if some robot arm leaves its keypad
+    fn replay(&self, presses: &str) -> Result<String, String> {
+        let mut arms: Vec<(i32, i32)> = self.keypads.iter().map(|k| k.keys[&'A']).collect();
+        let mut output = String::new();
+        for (i, c) in presses.chars().enumerate() {
+            let mut level = self.keypads.len() - 1;
+            let mut key = c;
+            loop {
+                if level == 0 {
+                    output.push(key);
+                    break;
+                }
+                let arm = &mut arms[level - 1];
+                let keypad = &self.keypads[level - 1];
+                match key {
+                    // Press the key the controlled arm points at
+                    'A' => {
+                        key = keypad.key_at(*arm).unwrap();
+                        level -= 1;
+                        continue;
+                    }
+                    '<' => arm.0 -= 1,
+                    '>' => arm.0 += 1,
+                    '^' => arm.1 -= 1,
+                    'v' => arm.1 += 1,
+                    _ => return Err(format!("Unknown control key {key} at press {i}")),
+                }
+                if keypad.key_at(*arm).is_none() {
+                    return Err(format!("Arm {} left its keypad at press {i}", level - 1));
+                }
+                break;
+            }
+        }
+        Ok(output)
+    }
 }
+
 // Parse sample input
 fn _sample_input() -> Vec<String> {
     let data = "029A
@@ -252,71 +255,53 @@ fn day_input() -> Vec<String> {
     process_input(read_input(DAY))
 }
 
-fn process_line(
-    line: &String,
-    start: char,
-    paths: &HashMap<(char, char), Vec<String>>,
-) -> Vec<String> {
-    let mut parts = vec![];
-    let mut prev = start;
-    for c in line.chars() {
-        let p = paths.get(&(prev, c)).unwrap();
-        parts.push(p);
-        prev = c;
-    }
-    let choices: Vec<String> = parts
-        .iter()
-        .map(|v| v.iter())
-        .multi_cartesian_product()
-        .map(|p| p.into_iter().cloned().collect::<String>())
-        .collect();
-    choices
+struct CodeComplexity {
+    code: String,
+    number: u64,
+    length: BigUint,
+    complexity: BigUint,
 }
 
-fn calculate_keypresses(input: &Vec<String>, directional_robots: usize) -> u64 {
-    let paths = possible_paths();
-    let paths2 = possible_paths2();
-    let mut memo = HashMap::new();
+fn breakdown(input: &[String], directional_robots: usize) -> Vec<CodeComplexity> {
+    let mut chain = KeypadChain::door(directional_robots);
     input
         .iter()
         .map(|line| {
             let number = line.split_at(3).0.parse::<u64>().unwrap();
-            let choices = process_line(line, 'A', &paths);
-            //println!("Choices: {:?}", choices);
-            let len: u64 = choices
-                .iter()
-                .map(|choice| {
-                    choice
-                        .chars()
-                        .into_iter()
-                        .fold(('A', 0), |(prev, acc), c| {
-                            (
-                                c,
-                                acc + required_keypresses(
-                                    prev,
-                                    c,
-                                    &paths2,
-                                    directional_robots - 1,
-                                    &mut memo,
-                                ),
-                            )
-                        })
-                        .1
-                })
-                .min()
-                .unwrap();
-            //println!("Length: {len}");
-            number * len
+            let length = chain.sequence_cost(0, line);
+            CodeComplexity {
+                code: line.clone(),
+                number,
+                complexity: &length * number,
+                length,
+            }
         })
+        .collect()
+}
+
+fn print_breakdown(rows: &[CodeComplexity]) {
+    println!("code  number  length  complexity");
+    for row in rows {
+        println!(
+            "{}  {}  {}  {}",
+            row.code, row.number, row.length, row.complexity
+        );
+    }
+}
+
+fn calculate_keypresses(input: &[String], directional_robots: usize) -> BigUint {
+    breakdown(input, directional_robots)
+        .into_iter()
+        .map(|row| row.complexity)
         .sum()
 }
 
-fn part1(input: &Vec<String>) -> u64 {
-    calculate_keypresses(input, 2)
+fn part1(input: &[String]) -> u128 {
+    calculate_keypresses(input, 2).try_into().unwrap()
 }
 
-fn part2(input: &Vec<String>) -> u64 {
-    calculate_keypresses(input, 25)
+fn part2(input: &[String]) -> u128 {
+    calculate_keypresses(input, 25).try_into().unwrap()
 }
 
 #[cfg(test)]
@@ -329,6 +314,107 @@ mod tests {
         assert_eq!(126384, part1(&input));
     }
 
+    #[test]
+    fn test_keypad_paths() {
+        let numeric = Keypad::from_layout(&NUMERIC_LAYOUT);
+        assert_eq!(11, numeric.keys.len());
+        let paths = &numeric.paths;
+        assert_eq!(vec!["A"], paths[&('5', '5')]);
+        assert_eq!(vec!["<A"], paths[&('A', '0')]);
+        // Paths never pass the gap in the bottom-left corner
+        assert_eq!(
+            vec!["^<<A", "<^<A"],
+            paths[&('A', '1')].iter().sorted().rev().collect_vec()
+        );
+        assert_eq!(
+            vec![">>vA", ">v>A"],
+            paths[&('1', 'A')].iter().sorted().collect_vec()
+        );
+
+        let directional = Keypad::from_layout(&DIRECTIONAL_LAYOUT);
+        assert_eq!(
+            vec!["v<<A", "<v<A"],
+            directional.paths[&('A', '<')]
+                .iter()
+                .sorted()
+                .rev()
+                .collect_vec()
+        );
+
+        // Large layouts only generate distinct interleavings: C(12, 6) corner to corner
+        let large = Keypad::from_layout(&[
+            "abcdefg", "hijklmn", "opqrstu", "vwxyz01", "2345678", "9BCDEFG", "HIJKLMN",
+        ]);
+        assert_eq!(924, large.paths[&('a', 'N')].len());
+        assert!(large.paths[&('a', 'N')].iter().all_unique());
+        assert_eq!(1, large.paths[&('a', 'g')].len());
+    }
+
+    #[test]
+    fn test_chains() {
+        // Human typing directly on the door keypad
+        let mut chain = KeypadChain::new(vec![Keypad::from_layout(&NUMERIC_LAYOUT)]);
+        assert_eq!(BigUint::from(4u32), chain.sequence_cost(0, "029A"));
+
+        // Human controlling one robot: <A^A>^^AvvvA
+        let mut chain = KeypadChain::door(0);
+        assert_eq!(BigUint::from(12u32), chain.sequence_cost(0, "029A"));
+        assert_eq!(
+            BigUint::from(68u32),
+            KeypadChain::door(2).sequence_cost(0, "029A")
+        );
+
+        // Custom keypads: a 2x2 pad with a gap controlled through a directional pad
+        let mut chain = KeypadChain::new(vec![
+            Keypad::from_layout(&["1 ", "2A"]),
+            Keypad::from_layout(&DIRECTIONAL_LAYOUT),
+        ]);
+        // A -> 1 must go <^ (going ^< would point at the gap)
+        assert_eq!(vec!["<^A"], chain.keypads[0].paths[&('A', '1')]);
+        assert_eq!(BigUint::from(3u32 + 2 + 2), chain.sequence_cost(0, "12A"));
+    }
+
+    #[test]
+    fn test_expand_and_replay() {
+        let mut chain = KeypadChain::door(0);
+        let sequence = chain.expand(0, "029A");
+        assert_eq!(12, sequence.len());
+        assert_eq!(Ok("029A".to_string()), chain.replay(&sequence));
+
+        // Puzzle example for two robots
+        let chain = KeypadChain::door(2);
+        let example = "<vA<AA>>^AvAA<^A>A<v<A>>^AvA^A<vA>^A<v<A>^A>AAvA^A<v<A>A>^AAAvA<^A>A";
+        assert_eq!(Ok("029A".to_string()), chain.replay(example));
+
+        let mut chain = KeypadChain::door(2);
+        for code in _sample_input() {
+            let sequence = chain.expand(0, &code);
+            assert_eq!(chain.sequence_cost(0, &code), sequence.len().into());
+            assert_eq!(Ok(code), chain.replay(&sequence));
+        }
+
+        // The door robot would point at the gap when moving left from 0
+        let chain = KeypadChain::door(0);
+        assert_eq!(Ok("0".to_string()), chain.replay("<A"));
+        assert!(chain.replay("<<A").is_err());
+    }
+
+    #[test]
+    fn test_deep_chains() {
+        let input = _sample_input();
+        let rows = breakdown(&input, 2);
+        assert_eq!("029A", rows[0].code);
+        assert_eq!(29, rows[0].number);
+        assert_eq!(BigUint::from(68u32), rows[0].length);
+        assert_eq!(BigUint::from(68u32 * 29), rows[0].complexity);
+
+        // Lengths grow by a roughly constant factor per robot
+        let shallow = calculate_keypresses(&input, 100);
+        let deep = calculate_keypresses(&input, 101);
+        assert!(shallow > BigUint::from(u128::MAX));
+        assert!(&shallow * 2u32 < deep && deep < &shallow * 3u32);
+    }
+
     #[test]
     fn real_part1() {
         let input = day_input();
@@ -1,18 +1,35 @@
-use std::{
-    collections::{HashMap, HashSet},
-    ops::AddAssign,
-};
-
-use aoc2024::common::read_input;
+use aoc2024::common::{arg_value, read_input};
 
 const DAY: u32 = 22;
 
 pub fn main() {
     let input = day_input();
-    let part1_result = part1(&input);
+    let part1_result = part1(&input, &DEFAULT_CONFIG);
     println!("Day {DAY}, part 1: {part1_result}");
-    let part2_result = part2(&input);
+    let part2_result = part2(&input, &DEFAULT_CONFIG);
     println!("Day {DAY}, part 2: {part2_result}");
+
+    // Each buyer's secret after an arbitrary number of steps
+    if let Some(steps) = arg_value("--secret-at") {
+        let steps: usize = steps.parse().unwrap();
+        for &secret in &input {
+            println!("{secret}: {}", nth_secret(secret, steps, &DEFAULT_CONFIG));
+        }
+    }
+
+    if let Some(k) = arg_value("--top") {
+        let report = market_report(&input, k.parse().unwrap(), &DEFAULT_CONFIG);
+        for (changes, total) in &report.top {
+            println!("{changes:?}: {total}");
+        }
+        println!("Best sequence {:?}", report.best);
+        for (secret, price) in input.iter().zip(&report.prices) {
+            match price {
+                Some(price) => println!("{secret}: {price}"),
+                None => println!("{secret}: -"),
+            }
+        }
+    }
 }
 
 // Parse input
@@ -37,69 +54,234 @@ fn day_input() -> Vec<i64> {
     process_input(read_input(DAY))
 }
 
+#[derive(Clone, Copy, Debug)]
+enum Shift {
+    Left(u32),
+    Right(u32),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PrngConfig {
+    shifts: [Shift; 3],
+    modulus_bits: u32, // secrets are kept modulo 2^modulus_bits
+    iterations: usize,
+    window: usize, // price changes per sequence
+}
+
+const DEFAULT_CONFIG: PrngConfig = PrngConfig {
+    shifts: [Shift::Left(6), Shift::Right(5), Shift::Left(11)],
+    modulus_bits: 24,
+    iterations: 2000,
+    window: 4,
+};
+
 fn mix(value: i64, secret: i64) -> i64 {
     value ^ secret
 }
 
-fn prune(value: i64) -> i64 {
-    value & 0xffffff
+fn prune(value: i64, config: &PrngConfig) -> i64 {
+    value & ((1 << config.modulus_bits) - 1)
 }
 
-fn evolve(value: i64) -> i64 {
-    let phase1 = prune(mix(value << 6, value));
-    let phase2 = prune(mix(phase1 >> 5, phase1));
-    prune(mix(phase2 << 11, phase2))
+fn evolve(value: i64, config: &PrngConfig) -> i64 {
+    config.shifts.iter().fold(value, |v, shift| {
+        let shifted = match *shift {
+            Shift::Left(n) => v << n,
+            Shift::Right(n) => v >> n,
+        };
+        prune(mix(shifted, v), config)
+    })
 }
 
-fn part1(input: &Vec<i64>) -> i64 {
-    input
-        .iter()
-        .map(|v| {
-            let mut value = *v;
-            for _ in 0..2000 {
-                value = evolve(value);
-            }
-            value
-        })
-        .sum()
+// Linear map over GF(2), stored as the image of each unit bit
+#[derive(Clone, PartialEq, Debug)]
+struct BitMatrix {
+    columns: Vec<i64>,
 }
 
-// Returns the appearance of the pattern and the first corresponding price
-fn pattern_values(value: i64) -> Vec<(Vec<i64>, i64)> {
-    let mut value = value;
-    let mut values = vec![value % 10];
-    for _ in 0..(2000 - 1) {
-        value = evolve(value);
-        values.push(value % 10); // last digit
+impl BitMatrix {
+    fn identity(bits: u32) -> BitMatrix {
+        BitMatrix {
+            columns: (0..bits).map(|b| 1 << b).collect(),
+        }
     }
 
-    // Calculate changes
-    let changes = values.windows(2).map(|v| v[1] - v[0]).collect::<Vec<i64>>();
+    // A single evolve step, which only shifts and xors bits
+    fn step(config: &PrngConfig) -> BitMatrix {
+        BitMatrix {
+            columns: (0..config.modulus_bits)
+                .map(|b| evolve(1 << b, config))
+                .collect(),
+        }
+    }
 
-    let mut seen = HashSet::new();
+    fn apply(&self, value: i64) -> i64 {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(|(b, _)| value >> b & 1 == 1)
+            .fold(0, |acc, (_, c)| acc ^ c)
+    }
+
+    // Apply other first, then self
+    fn compose(&self, other: &BitMatrix) -> BitMatrix {
+        BitMatrix {
+            columns: other.columns.iter().map(|&c| self.apply(c)).collect(),
+        }
+    }
+
+    fn power(&self, mut n: usize) -> BitMatrix {
+        let mut result = BitMatrix::identity(self.columns.len() as u32);
+        let mut base = self.clone();
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.compose(&base);
+            }
+            base = base.compose(&base);
+            n >>= 1;
+        }
+        result
+    }
+}
+
+// Matrix taking a secret n steps ahead in O(log n) compositions
+fn jump_ahead(n: usize, config: &PrngConfig) -> BitMatrix {
+    BitMatrix::step(config).power(n)
+}
+
+// The n-th secret after the given one
+fn nth_secret(secret: i64, n: usize, config: &PrngConfig) -> i64 {
+    jump_ahead(n, config).apply(prune(secret, config))
+}
+
+fn part1(input: &[i64], config: &PrngConfig) -> i64 {
+    let jump = jump_ahead(config.iterations, config);
+    input.iter().map(|&v| jump.apply(prune(v, config))).sum()
+}
+
+// Price changes lie in -9..=9, so a window of changes maps to a base-19 number
+const CHANGE_VALUES: usize = 19;
+
+fn sequence_count(config: &PrngConfig) -> usize {
+    CHANGE_VALUES.pow(config.window as u32)
+}
+
+fn encode_sequence(changes: &[i64]) -> usize {
     changes
-        .windows(4)
-        .enumerate()
-        .filter_map(|(i, w)| {
-            if seen.insert(w.to_vec()) {
-                Some((w.to_vec(), values[i + 4]))
-            } else {
-                None
+        .iter()
+        .fold(0, |acc, c| acc * CHANGE_VALUES + (c + 9) as usize)
+}
+
+fn decode_sequence(mut index: usize, window: usize) -> Vec<i64> {
+    let mut changes = vec![0; window];
+    for change in changes.iter_mut().rev() {
+        *change = (index % CHANGE_VALUES) as i64 - 9;
+        index /= CHANGE_VALUES;
+    }
+    changes
+}
+
+// Sequence index and price for every full window of changes of one buyer
+fn price_windows(secret: i64, config: &PrngConfig) -> impl Iterator<Item = (usize, i64)> + '_ {
+    let sequences = sequence_count(config);
+    let mut value = secret;
+    let mut price = value % 10;
+    let mut index = 0;
+    (1..config.iterations).filter_map(move |i| {
+        value = evolve(value, config);
+        let next = value % 10;
+        index = (index * CHANGE_VALUES + (next - price + 9) as usize) % sequences;
+        price = next;
+        (i >= config.window).then_some((index, price))
+    })
+}
+
+// Add each buyer's first price for every change sequence into totals. `seen`
+// holds the last buyer (plus one) that has sold at a sequence, so it never needs
+// clearing between buyers.
+fn accumulate_prices(buyers: &[i64], totals: &mut [i64], seen: &mut [u32], config: &PrngConfig) {
+    for (buyer, &secret) in buyers.iter().enumerate() {
+        let stamp = buyer as u32 + 1;
+        for (index, price) in price_windows(secret, config) {
+            if seen[index] != stamp {
+                seen[index] = stamp;
+                totals[index] += price;
             }
-        })
-        .collect::<Vec<(Vec<i64>, i64)>>()
+        }
+    }
 }
 
-fn part2(input: &Vec<i64>) -> i64 {
-    let mut result_map = HashMap::new();
+// Total bananas for every change sequence, split over the given number of threads
+fn sequence_totals(input: &[i64], threads: usize, config: &PrngConfig) -> Vec<i64> {
+    let sequences = sequence_count(config);
+    let chunk_size = input.len().div_ceil(threads.max(1)).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = input
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut totals = vec![0; sequences];
+                    let mut seen = vec![0; sequences];
+                    accumulate_prices(chunk, &mut totals, &mut seen, config);
+                    totals
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .reduce(|mut acc, totals| {
+                acc.iter_mut().zip(totals).for_each(|(a, t)| *a += t);
+                acc
+            })
+            .unwrap_or_else(|| vec![0; sequences])
+    })
+}
+
+fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Price each buyer sells at for a sequence, if it ever occurs
+fn buyer_prices(input: &[i64], changes: &[i64], config: &PrngConfig) -> Vec<Option<i64>> {
+    let target = encode_sequence(changes);
     input
         .iter()
-        .map(|v| pattern_values(*v))
-        .flatten()
-        .for_each(|(series, value)| {
-            result_map.entry(series).or_insert(0).add_assign(value);
-        });
-    result_map.values().max().unwrap().clone()
+        .map(|&secret| {
+            price_windows(secret, config)
+                .find(|&(index, _)| index == target)
+                .map(|(_, price)| price)
+        })
+        .collect()
+}
+
+struct MarketReport {
+    best: Vec<i64>,
+    prices: Vec<Option<i64>>, // per buyer, for the best sequence
+    top: Vec<(Vec<i64>, i64)>,
+}
+
+// The k most profitable sequences, with the buyers' prices for the winner
+fn market_report(input: &[i64], k: usize, config: &PrngConfig) -> MarketReport {
+    let totals = sequence_totals(input, default_threads(), config);
+    let mut ranked: Vec<usize> = (0..totals.len()).collect();
+    ranked.sort_by_key(|&i| (std::cmp::Reverse(totals[i]), i));
+    let top: Vec<(Vec<i64>, i64)> = ranked
+        .iter()
+        .take(k.max(1))
+        .map(|&i| (decode_sequence(i, config.window), totals[i]))
+        .collect();
+    let best = top[0].0.clone();
+    MarketReport {
+        prices: buyer_prices(input, &best, config),
+        best,
+        top,
+    }
+}
+
+fn part2(input: &[i64], config: &PrngConfig) -> i64 {
+    let totals = sequence_totals(input, default_threads(), config);
+    totals.into_iter().max().unwrap()
 }
 
 #[cfg(test)]
@@ -108,35 +290,95 @@ mod tests {
 
     #[test]
     fn test_helpers() {
-        println!("{:?}", 1 << 6);
-        println!("{:?}", 32 >> 5);
-        println!("{:?}", 1 << 11);
         assert_eq!(37, mix(15, 42));
-        assert_eq!(16113920, prune(100000000));
-        assert_eq!(15887950, evolve(123));
+        assert_eq!(16113920, prune(100000000, &DEFAULT_CONFIG));
+        assert_eq!(15887950, evolve(123, &DEFAULT_CONFIG));
     }
 
     #[test]
     fn test_part1() {
         let input = _sample_input();
-        assert_eq!(37327623, part1(&input));
+        assert_eq!(37327623, part1(&input, &DEFAULT_CONFIG));
     }
 
     #[test]
     fn test_part2() {
         let input = vec![1, 2, 3, 2024];
-        assert_eq!(23, part2(&input));
+        assert_eq!(23, part2(&input, &DEFAULT_CONFIG));
+    }
+
+    #[test]
+    fn test_sequence_totals() {
+        let input = vec![1, 2, 3, 2024];
+        // Changes -2,1,-1,3 give 7 + 7 + 9 bananas
+        let index = encode_sequence(&[-2, 1, -1, 3]);
+        let single = sequence_totals(&input, 1, &DEFAULT_CONFIG);
+        assert_eq!(23, single[index]);
+        assert_eq!(single, sequence_totals(&input, 3, &DEFAULT_CONFIG));
+        assert_eq!(single, sequence_totals(&input, 16, &DEFAULT_CONFIG));
+    }
+
+    #[test]
+    fn test_market_report() {
+        let input = vec![1, 2, 3, 2024];
+        assert_eq!(
+            vec![-2, 1, -1, 3],
+            decode_sequence(encode_sequence(&[-2, 1, -1, 3]), 4)
+        );
+        assert_eq!(
+            vec![9, -9, 0],
+            decode_sequence(encode_sequence(&[9, -9, 0]), 3)
+        );
+
+        let report = market_report(&input, 3, &DEFAULT_CONFIG);
+        assert_eq!(vec![-2, 1, -1, 3], report.best);
+        assert_eq!(vec![Some(7), Some(7), None, Some(9)], report.prices);
+        assert_eq!(3, report.top.len());
+        assert_eq!((report.best.clone(), 23), report.top[0]);
+        assert!(report.top.windows(2).all(|w| w[0].1 >= w[1].1));
+        for (changes, total) in &report.top {
+            let prices = buyer_prices(&input, changes, &DEFAULT_CONFIG);
+            assert_eq!(*total, prices.iter().flatten().sum::<i64>());
+        }
+    }
+
+    #[test]
+    fn test_jump_ahead() {
+        let expected = [
+            15887950, 16495136, 527345, 704524, 1553684, 12683156, 11100544, 12249484, 7753432,
+            5908254,
+        ];
+        for (n, &secret) in expected.iter().enumerate() {
+            assert_eq!(secret, nth_secret(123, n + 1, &DEFAULT_CONFIG));
+        }
+        assert_eq!(123, nth_secret(123, 0, &DEFAULT_CONFIG));
+        assert_eq!(8685429, nth_secret(1, 2000, &DEFAULT_CONFIG));
+
+        // Jumping agrees with stepping for other generators too
+        let config = PrngConfig {
+            shifts: [Shift::Left(3), Shift::Right(7), Shift::Left(2)],
+            modulus_bits: 16,
+            iterations: 50,
+            window: 3,
+        };
+        let stepped = (0..1000).fold(4321, |v, _| evolve(v, &config));
+        assert_eq!(stepped, nth_secret(4321, 1000, &config));
+        assert_eq!(
+            jump_ahead(1000, &config),
+            jump_ahead(400, &config).compose(&jump_ahead(600, &config))
+        );
+        assert_eq!(19 * 19 * 19, sequence_totals(&[4321], 2, &config).len());
     }
 
     #[test]
     fn real_part1() {
         let input = day_input();
-        assert_eq!(20506453102, part1(&input));
+        assert_eq!(20506453102, part1(&input, &DEFAULT_CONFIG));
     }
 
     #[test]
     fn real_part2() {
         let input = day_input();
-        assert_eq!(2423, part2(&input));
+        assert_eq!(2423, part2(&input, &DEFAULT_CONFIG));
     }
 }
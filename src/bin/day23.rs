@@ -1,7 +1,8 @@
-use std::{collections::HashSet, usize};
+use std::collections::HashSet;
 
-use aoc2024::common::read_input;
+use aoc2024::common::{arg_value, graph::Graph, has_flag, read_input};
 use itertools::Itertools;
+use regex::Regex;
 
 const DAY: u32 = 23;
 
@@ -11,40 +12,33 @@ pub fn main() {
     println!("Day {DAY}, part 1: {part1_result}");
     let part2_result = part2(&input);
     println!("Day {DAY}, part 2: {part2_result}");
-}
-
-// Parse input
-fn process_input(input: Vec<String>) -> (Vec<Vertex>, Vec<(usize, usize)>) {
-    let mut vertices: Vec<Vertex> = Vec::new();
 
-    fn pos_or_insert(value: &str, vertices: &mut Vec<Vertex>) -> usize {
-        let value_tuple = (value.chars().nth(0).unwrap(), value.chars().nth(1).unwrap());
-        vertices
-            .iter()
-            .position(|x| x.name.0 == value_tuple.0 && x.name.1 == value_tuple.1)
-            .unwrap_or_else(||{
-                vertices.push(Vertex {
-                    name: value_tuple,
-                });
-                vertices.len() - 1
-            })
+    if let Some(k) = arg_value("--k") {
+        let k: usize = k.parse().unwrap();
+        let filter = NameFilter::from_args().unwrap_or(NameFilter::Prefix(String::new()));
+        let count = count_cliques(&input, k, &filter);
+        println!("{k}-cliques matching filter: {count}");
     }
 
-    let edges: Vec<(usize, usize)> = input
-        .iter()
-        .flat_map(|s| {
-            let splitted: Vec<&str> = s.split("-").collect();
-            let left = pos_or_insert(splitted[0], &mut vertices);
-            let right = pos_or_insert(splitted[1], &mut vertices);
-            [(left, right), (right, left)] // undirected graph
-        })
-        .collect();
+    if has_flag("--all-cliques") {
+        for clique in maximal_cliques(&input) {
+            println!("{}", clique_name(&clique, &input));
+        }
+    }
+}
 
-    (vertices, edges)
+// Parse input
+fn process_input(input: Vec<String>) -> Graph<String> {
+    let mut graph = Graph::undirected();
+    for line in &input {
+        let (left, right) = line.split_once('-').unwrap();
+        graph.add_edge(left.to_string(), right.to_string());
+    }
+    graph
 }
 
 // Parse sample input
-fn _sample_input() -> (Vec<Vertex>, Vec<(usize, usize)>) {
+fn _sample_input() -> Graph<String> {
     let data = "kh-tc
 qp-kh
 de-cg
@@ -84,82 +78,178 @@ td-yn"
 }
 
 // Parse day's input
-fn day_input() -> (Vec<Vertex>, Vec<(usize, usize)>) {
+fn day_input() -> Graph<String> {
     process_input(read_input(DAY))
 }
 
-#[derive(Clone, Copy)]
-struct Vertex {
-    name: (char, char),
-}
-
-// Find 3-cliques
-fn find_cliques(edges: &Vec<(usize, usize)>) -> Vec<[usize; 3]> {
-    let mut cliques: Vec<[usize; 3]> = edges
+// Bron-Kerbosch with pivoting: reports every maximal clique extending `clique`
+// with vertices from `candidates`, none of which extends with `excluded`
+fn bron_kerbosch(
+    graph: &Graph<String>,
+    clique: &mut Vec<usize>,
+    mut candidates: HashSet<usize>,
+    mut excluded: HashSet<usize>,
+    report: &mut dyn FnMut(&[usize]),
+) {
+    if candidates.is_empty() {
+        if excluded.is_empty() {
+            report(clique);
+        }
+        return;
+    }
+    // Pivot on the vertex covering most candidates; its neighbors need no branch
+    let pivot = candidates
+        .union(&excluded)
+        .max_by_key(|&&u| candidates.iter().filter(|&&c| graph.has_edge(u, c)).count())
+        .copied()
+        .unwrap();
+    let branches: Vec<usize> = candidates
         .iter()
-        .flat_map(|&(v0, v1)| {
-            edges
-                .iter()
-                .filter(|&e| {
-                    e.0 == v0
-                        && e.1 != v1
-                        && edges.iter().find(|&e1| e1.0 == e.1 && e1.1 == v1).is_some()
-                })
-                .map(|e| [v0, v1, e.1])
-                .collect::<Vec<_>>()
-        })
+        .copied()
+        .filter(|&c| !graph.has_edge(pivot, c))
         .collect();
-    cliques.iter_mut().for_each(|c| c.sort());
-    cliques.iter().unique().cloned().collect()
+    for v in branches {
+        let neighbors = |set: &HashSet<usize>| -> HashSet<usize> {
+            graph
+                .neighbors(v)
+                .iter()
+                .copied()
+                .filter(|u| set.contains(u))
+                .collect()
+        };
+        clique.push(v);
+        bron_kerbosch(
+            graph,
+            clique,
+            neighbors(&candidates),
+            neighbors(&excluded),
+            report,
+        );
+        clique.pop();
+        candidates.remove(&v);
+        excluded.insert(v);
+    }
+}
+
+fn all_vertices(graph: &Graph<String>) -> HashSet<usize> {
+    (0..graph.len()).collect()
+}
+
+// All maximal cliques, each sorted by vertex index
+fn maximal_cliques(graph: &Graph<String>) -> Vec<Vec<usize>> {
+    let mut cliques = Vec::new();
+    bron_kerbosch(
+        graph,
+        &mut Vec::new(),
+        all_vertices(graph),
+        HashSet::new(),
+        &mut |c| cliques.push(c.iter().copied().sorted().collect()),
+    );
+    cliques
 }
 
-// Extend clique with some vertex
+fn maximum_clique(graph: &Graph<String>) -> Vec<usize> {
+    let mut best: Vec<usize> = Vec::new();
+    bron_kerbosch(
+        graph,
+        &mut Vec::new(),
+        all_vertices(graph),
+        HashSet::new(),
+        &mut |c| {
+            if c.len() > best.len() {
+                best = c.to_vec();
+            }
+        },
+    );
+    best
+}
+
+// Password: sorted computer names joined by commas
+fn clique_name(clique: &[usize], graph: &Graph<String>) -> String {
+    clique.iter().map(|&i| graph.name(i)).sorted().join(",")
+}
+
+// Extend an ascending clique with higher neighbors until it has k vertices
 fn extend_clique(
-    current: &Vec<usize>,
-    vertices: &Vec<Vertex>,
-    edges: &Vec<(usize, usize)>,
-) -> Option<usize> {
-    (0..vertices.len()).find(|&i| {
-        !current.contains(&i) // not already in the clique
-            && current.iter().all(|&c| { 
-                // all vertices in the clique are connected to the new vertex
-                edges.iter().find(|&e| e.0 == c && e.1 == i).is_some()
+    graph: &Graph<String>,
+    clique: &mut Vec<usize>,
+    k: usize,
+    found: &mut Vec<Vec<usize>>,
+) {
+    if clique.len() == k {
+        found.push(clique.clone());
+        return;
+    }
+    let candidates: Vec<usize> = match clique.first() {
+        Some(&first) => graph
+            .neighbors(first)
+            .iter()
+            .copied()
+            .filter(|&v| {
+                v > *clique.last().unwrap() && clique.iter().all(|&c| graph.has_edge(c, v))
             })
-    })
+            .collect(),
+        None => (0..graph.len()).collect(),
+    };
+    for v in candidates {
+        clique.push(v);
+        extend_clique(graph, clique, k, found);
+        clique.pop();
+    }
 }
 
-fn part1(input: &(Vec<Vertex>, Vec<(usize, usize)>)) -> usize {
-    let (vertices, edges) = input;
-    let t_vertices = vertices
-        .iter()
-        .enumerate()
-        .filter_map(|(i, v)| if v.name.0 == 't' { Some(i) } else { None })
-        .collect::<Vec<_>>();
-    let cliques = find_cliques(edges);
-    cliques
+// All cliques of exactly k vertices, each as ascending ids
+fn k_cliques(graph: &Graph<String>, k: usize) -> Vec<Vec<usize>> {
+    let mut found = Vec::new();
+    extend_clique(graph, &mut Vec::new(), k, &mut found);
+    found
+}
+
+// Selects computers of interest by name
+enum NameFilter {
+    Prefix(String),
+    Regex(Regex),
+    Set(HashSet<String>),
+}
+
+impl NameFilter {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NameFilter::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            NameFilter::Regex(regex) => regex.is_match(name),
+            NameFilter::Set(names) => names.contains(name),
+        }
+    }
+
+    // From `--prefix P`, `--regex R` or `--names a,b,c`
+    fn from_args() -> Option<NameFilter> {
+        if let Some(prefix) = arg_value("--prefix") {
+            Some(NameFilter::Prefix(prefix))
+        } else if let Some(regex) = arg_value("--regex") {
+            Some(NameFilter::Regex(
+                Regex::new(&regex).expect("invalid regex"),
+            ))
+        } else {
+            arg_value("--names")
+                .map(|names| NameFilter::Set(names.split(',').map(|s| s.to_string()).collect()))
+        }
+    }
+}
+
+// Number of k-cliques containing at least one computer matching the filter
+fn count_cliques(graph: &Graph<String>, k: usize, filter: &NameFilter) -> usize {
+    k_cliques(graph, k)
         .iter()
-        .filter(|&c| c.iter().any(|v| t_vertices.contains(v)))
+        .filter(|c| c.iter().any(|&v| filter.matches(graph.name(v))))
         .count()
 }
 
-fn part2(input: &(Vec<Vertex>, Vec<(usize, usize)>)) -> String {
-    let (vertices, edges) = input;
-    let mut explored: HashSet<usize> = HashSet::new();
-    (0..vertices.len())
-        .map(|i| {
-            let mut clique = vec![i];
-            while let Some(next) = extend_clique(&clique, vertices, edges) {
-                clique.push(next);
-            }
-            explored.extend(clique.iter());
-            clique
-        })
-        .max_by_key(|v| v.len())
-        .unwrap()
-        .iter()
-        .map(|&i| format!("{}{}", vertices[i].name.0, vertices[i].name.1))
-        .sorted()
-        .join(",")
+fn part1(input: &Graph<String>) -> usize {
+    count_cliques(input, 3, &NameFilter::Prefix("t".to_string()))
+}
+
+fn part2(input: &Graph<String>) -> String {
+    clique_name(&maximum_clique(input), input)
 }
 
 #[cfg(test)]
@@ -167,12 +257,87 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_helper() {
-        let input = day_input();
-        let (vertices, edges) = &input;
-        let current = vec![0];
-        let result = extend_clique(&current, vertices, edges);
-        println!("{:?}", result);
+    fn test_maximal_cliques() {
+        let graph = _sample_input();
+        let cliques = maximal_cliques(&graph);
+        let names: HashSet<String> = cliques.iter().map(|c| clique_name(c, &graph)).collect();
+        assert_eq!(cliques.len(), names.len());
+        assert!(names.contains("co,de,ka,ta"));
+        assert!(names.contains("kh,qp,ub"));
+
+        // Every clique is complete and cannot be extended
+        for clique in &cliques {
+            assert!(clique
+                .iter()
+                .tuple_combinations()
+                .all(|(&a, &b)| graph.has_edge(a, b)));
+            assert!((0..graph.len())
+                .all(|v| clique.contains(&v) || !clique.iter().all(|&c| graph.has_edge(c, v))));
+        }
+        let largest = cliques.iter().map(|c| c.len()).max().unwrap();
+        assert_eq!(largest, maximum_clique(&graph).len());
+    }
+
+    #[test]
+    fn test_k_cliques() {
+        let graph = _sample_input();
+        let triangles: Vec<Vec<usize>> = graph.triangles().iter().map(|t| t.to_vec()).collect();
+        assert_eq!(triangles.len(), k_cliques(&graph, 3).len());
+        assert!(k_cliques(&graph, 3).iter().all(|c| triangles.contains(c)));
+        assert_eq!(32, k_cliques(&graph, 2).len());
+        assert_eq!(graph.len(), k_cliques(&graph, 1).len());
+        assert_eq!(
+            vec!["co,de,ka,ta"],
+            k_cliques(&graph, 4)
+                .iter()
+                .map(|c| clique_name(c, &graph))
+                .collect::<Vec<_>>()
+        );
+        assert!(k_cliques(&graph, 5).is_empty());
+    }
+
+    #[test]
+    fn test_name_filters() {
+        let graph = process_input(
+            "alpha-beta beta-gamma gamma-alpha gamma-delta delta-beta tango-alpha"
+                .split(' ')
+                .map(|s| s.to_string())
+                .collect(),
+        );
+        let everything = NameFilter::Prefix(String::new());
+        assert_eq!(2, count_cliques(&graph, 3, &everything));
+        assert_eq!(0, count_cliques(&graph, 4, &everything));
+        assert_eq!(
+            1,
+            count_cliques(&graph, 3, &NameFilter::Prefix("al".to_string()))
+        );
+        assert_eq!(
+            2,
+            count_cliques(
+                &graph,
+                3,
+                &NameFilter::Regex(Regex::new("^(ga|de)").unwrap())
+            )
+        );
+        let set = NameFilter::Set(["tango".to_string(), "delta".to_string()].into());
+        assert_eq!(1, count_cliques(&graph, 3, &set));
+        assert_eq!(3, count_cliques(&graph, 2, &set));
+        assert_eq!(
+            0,
+            count_cliques(&graph, 3, &NameFilter::Prefix("t".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_greedy_trap() {
+        // Greedy growth from ab takes cd and gets stuck; the largest clique is ef,gh,ij,kl
+        let input = process_input(
+            "ab-cd ab-ef ab-gh ef-gh ef-ij ef-kl gh-ij gh-kl ij-kl"
+                .split(' ')
+                .map(|s| s.to_string())
+                .collect(),
+        );
+        assert_eq!("ef,gh,ij,kl", part2(&input));
     }
 
     #[test]
@@ -1,6 +1,8 @@
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Write};
+use std::fs;
 
-use aoc2024::common::read_input;
+use aoc2024::common::{arg_value, has_flag, read_input};
 use itertools::Itertools;
 use regex::Regex;
 
@@ -10,61 +12,266 @@ pub fn main() {
     let input = day_input();
     let part1_result = part1(&input);
     println!("Day {DAY}, part 1: {part1_result}");
-    let part2_result = part2(&input, false);
+    let max_swaps = arg_value("--swaps")
+        .map(|s| s.parse().expect("Invalid swap count"))
+        .unwrap_or(MAX_SWAPS);
+    // The swap search is the slow part, so run it once for every output below
+    let repair = repair_adder(&input.1, max_swaps).expect("No verified repair found");
+    let part2_result = part2(&repair);
     println!("Day {DAY}, part 2: {part2_result}");
+
+    // Write the repaired circuit in the puzzle's input format
+    if let Some(path) = arg_value("--repair") {
+        let lines = to_puzzle_format(&input.0, &repair.netlist);
+        fs::write(&path, lines.join("\n") + "\n").expect("Failed to write repaired netlist");
+        println!("Wrote {path} with swaps {:?}", repair.swaps);
+    }
+
+    // Optional exports for inspecting the circuit in other tools
+    if let Some(path) = arg_value("--dot") {
+        let swapped = swapped_wires(&repair.swaps);
+        fs::write(&path, to_dot(&input.1, &swapped)).expect("Failed to write DOT file");
+        println!("Wrote {path}");
+    }
+    if has_flag("--simulate") {
+        let netlist = &input.1;
+        let mut vectors = exhaustive_vectors(netlist.input_bits());
+        vectors.extend(random_vectors(netlist.input_bits(), 1000, 0x2024_1224));
+        let failures = run_vectors(netlist, &vectors);
+        println!(
+            "{} of {} test vectors failed",
+            failures.len(),
+            vectors.len()
+        );
+        print_failures(&failures);
+    }
+    if let Some(path) = arg_value("--verilog") {
+        fs::write(&path, to_verilog(&input.1, "day24")).expect("Failed to write Verilog file");
+        println!("Wrote {path}");
+    }
 }
 
-#[derive(PartialEq, Clone, Copy, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 struct Gate {
-    left: [char; 3],
+    left: String,
     cmd: char, // A(nd), O(r), X(or)
-    right: [char; 3],
-    target: [char; 3],
+    right: String,
+    target: String,
 }
 
-// Parse input
-fn process_input(input: Vec<String>) -> (HashMap<[char; 3], bool>, Vec<Gate>) {
+#[derive(PartialEq, Clone, Debug)]
+enum NetlistError {
+    Cycle(Vec<String>),      // wires forming a loop, in signal order
+    Undriven(String),        // wire read by a gate but not an input or gate output
+    MultipleDrivers(String), // wire written by more than one source
+    MissingInput(String),    // input wire without a value during evaluation
+}
+
+impl fmt::Display for NetlistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetlistError::Cycle(wires) => write!(f, "cycle through {}", wires.join(" -> ")),
+            NetlistError::Undriven(wire) => write!(f, "wire {wire} is not driven"),
+            NetlistError::MultipleDrivers(wire) => write!(f, "wire {wire} has multiple drivers"),
+            NetlistError::MissingInput(wire) => write!(f, "no value for input {wire}"),
+        }
+    }
+}
+
+// Validated circuit: gates are kept in topological order of evaluation
+#[derive(Clone, Debug)]
+struct Netlist {
+    inputs: Vec<String>,
+    gates: Vec<Gate>,
+    order: Vec<usize>,
+}
+
+impl Netlist {
+    fn new(
+        inputs: impl IntoIterator<Item = String>,
+        gates: Vec<Gate>,
+    ) -> Result<Netlist, NetlistError> {
+        let inputs: Vec<String> = inputs.into_iter().sorted().dedup().collect();
+        let input_set: HashSet<&str> = inputs.iter().map(|s| s.as_str()).collect();
+
+        // Map each wire to the gate driving it
+        let mut drivers: HashMap<&str, usize> = HashMap::new();
+        for (i, gate) in gates.iter().enumerate() {
+            if input_set.contains(gate.target.as_str()) || drivers.insert(&gate.target, i).is_some()
+            {
+                return Err(NetlistError::MultipleDrivers(gate.target.clone()));
+            }
+        }
+
+        // Kahn's algorithm over gate-to-gate dependencies
+        let mut pending = vec![0; gates.len()];
+        let mut consumers: Vec<Vec<usize>> = vec![Vec::new(); gates.len()];
+        for (i, gate) in gates.iter().enumerate() {
+            for wire in [&gate.left, &gate.right] {
+                if let Some(&driver) = drivers.get(wire.as_str()) {
+                    pending[i] += 1;
+                    consumers[driver].push(i);
+                } else if !input_set.contains(wire.as_str()) {
+                    return Err(NetlistError::Undriven(wire.clone()));
+                }
+            }
+        }
+        let mut queue: VecDeque<usize> = (0..gates.len()).filter(|&i| pending[i] == 0).collect();
+        let mut order = Vec::with_capacity(gates.len());
+        while let Some(i) = queue.pop_front() {
+            order.push(i);
+            for &next in &consumers[i] {
+                pending[next] -= 1;
+                if pending[next] == 0 {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        if order.len() < gates.len() {
+            // Every unresolved gate has an unresolved driver, so walking backwards must loop
+            let mut current = (0..gates.len()).find(|&i| pending[i] > 0).unwrap();
+            let mut path: Vec<usize> = Vec::new();
+            let cycle_start = loop {
+                if let Some(pos) = path.iter().position(|&g| g == current) {
+                    break pos;
+                }
+                path.push(current);
+                let gate = &gates[current];
+                current = [&gate.left, &gate.right]
+                    .iter()
+                    .filter_map(|w| drivers.get(w.as_str()))
+                    .copied()
+                    .find(|&d| pending[d] > 0)
+                    .unwrap();
+            };
+            let wires = path[cycle_start..]
+                .iter()
+                .rev()
+                .map(|&g| gates[g].target.clone())
+                .collect();
+            return Err(NetlistError::Cycle(wires));
+        }
+
+        Ok(Netlist {
+            inputs,
+            gates,
+            order,
+        })
+    }
+
+    // Copy of the circuit with the given gate outputs exchanged
+    fn with_swaps(&self, swaps: &[(String, String)]) -> Result<Netlist, NetlistError> {
+        let mut gates = self.gates.clone();
+        for (a, b) in swaps {
+            for gate in gates.iter_mut() {
+                if gate.target == *a {
+                    gate.target = b.clone();
+                } else if gate.target == *b {
+                    gate.target = a.clone();
+                }
+            }
+        }
+        Netlist::new(self.inputs.clone(), gates)
+    }
+
+    // Evaluate every gate once, given values for all inputs
+    fn evaluate(
+        &self,
+        values: &HashMap<String, bool>,
+    ) -> Result<HashMap<String, bool>, NetlistError> {
+        let mut map = HashMap::with_capacity(self.inputs.len() + self.gates.len());
+        for wire in &self.inputs {
+            let value = values
+                .get(wire)
+                .ok_or_else(|| NetlistError::MissingInput(wire.clone()))?;
+            map.insert(wire.clone(), *value);
+        }
+        for &i in &self.order {
+            eval_gate(&self.gates[i], &mut map);
+        }
+        Ok(map)
+    }
+
+    // Number of bits in the x input
+    fn input_bits(&self) -> usize {
+        self.inputs.iter().filter(|w| w.starts_with('x')).count()
+    }
+
+    // Evaluate with x and y inputs taken from the given numbers, returning z
+    fn evaluate_xy(&self, x: u64, y: u64) -> Result<u128, NetlistError> {
+        let values = self
+            .inputs
+            .iter()
+            .filter_map(|wire| {
+                let number = match wire.chars().next() {
+                    Some('x') => x,
+                    Some('y') => y,
+                    _ => return None,
+                };
+                let bit = wire[1..].parse::<usize>().ok()?;
+                Some((
+                    wire.clone(),
+                    bit < u64::BITS as usize && (number >> bit) & 1 == 1,
+                ))
+            })
+            .collect();
+        let map = self.evaluate(&values)?;
+        Ok(get_number('z', &map))
+    }
+}
+
+// Parse initial values and gates without validating the circuit
+fn parse_lines(input: &[String]) -> (HashMap<String, bool>, Vec<Gate>) {
     let initial = Regex::new(r"^(\S+): (\d)$").unwrap();
     let connection = Regex::new(r"^(\S+) (AND|OR|XOR) (\S+) -> (\S+)$").unwrap();
     let mut output = HashMap::new();
     let mut gates = Vec::new();
-    for line in &input {
-        if let Some(captures) = initial.captures(&line) {
-            let target: [char; 3] = captures
-                .get(1)
-                .unwrap()
-                .as_str()
-                .chars()
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap();
+    for line in input {
+        if let Some(captures) = initial.captures(line) {
+            let target = captures.get(1).unwrap().as_str().to_string();
             let value = captures.get(2).unwrap().as_str();
             output.insert(target, value == "1");
-        } else if let Some(captures) = connection.captures(&line) {
+        } else if let Some(captures) = connection.captures(line) {
             let left = captures.get(1).unwrap().as_str();
             let cmd = captures.get(2).unwrap().as_str();
             let right = captures.get(3).unwrap().as_str();
             let target = captures.get(4).unwrap().as_str();
             gates.push(Gate {
-                left: left.chars().collect::<Vec<_>>().try_into().unwrap(),
+                left: left.to_string(),
                 cmd: cmd.chars().next().unwrap(),
-                right: right.chars().collect::<Vec<_>>().try_into().unwrap(),
-                target: target.chars().collect::<Vec<_>>().try_into().unwrap(),
+                right: right.to_string(),
+                target: target.to_string(),
             });
         }
     }
-    // Insert zero gate
-    output.insert(['0', '0', '0'], false);
     (output, gates)
 }
 
+// Parse input
+fn process_input(input: Vec<String>) -> (HashMap<String, bool>, Netlist) {
+    let (initial, gates) = parse_lines(&input);
+    let netlist = Netlist::new(initial.keys().cloned(), gates)
+        .unwrap_or_else(|e| panic!("Invalid netlist: {e}"));
+    (initial, netlist)
+}
+
 // Parse day's input
-fn day_input() -> (HashMap<[char; 3], bool>, Vec<Gate>) {
+fn day_input() -> (HashMap<String, bool>, Netlist) {
     process_input(read_input(DAY))
 }
 
+fn cmd_name(cmd: char) -> &'static str {
+    match cmd {
+        'A' => "AND",
+        'O' => "OR",
+        'X' => "XOR",
+        _ => panic!("Unknown command"),
+    }
+}
+
 // Evaluate a gate, update map
-fn eval_gate(gate: &Gate, map: &mut HashMap<[char; 3], bool>) {
+fn eval_gate(gate: &Gate, map: &mut HashMap<String, bool>) {
     let value = match gate.cmd {
         'A' => map[&gate.left] & map[&gate.right],
         'O' => map[&gate.left] | map[&gate.right],
@@ -74,235 +281,489 @@ fn eval_gate(gate: &Gate, map: &mut HashMap<[char; 3], bool>) {
     map.insert(gate.target.to_owned(), value);
 }
 
-// Read number from specific prefix
-fn get_number(prefix: &str, map: &HashMap<[char; 3], bool>) -> usize {
-    let mut result = 0;
-    let x_pattern = Regex::new(r"^x(\d+)$").unwrap();
-    let y_pattern = Regex::new(r"^y(\d+)$").unwrap();
-    let z_pattern = Regex::new(r"^z(\d+)$").unwrap();
-    let pattern = match prefix {
-        "x" => x_pattern,
-        "y" => y_pattern,
-        "z" => z_pattern,
-        _ => panic!("Unknown prefix"),
-    };
-    for (key, value) in map {
-        let key_str: String = key.iter().collect();
-        if let Some(id) = pattern.captures(&key_str) {
-            if *value {
-                result += 1 << id.get(1).unwrap().as_str().parse::<usize>().unwrap();
-            }
-        }
-    }
-    result
+// Read number from wires with the given prefix followed by a bit index
+fn get_number(prefix: char, map: &HashMap<String, bool>) -> u128 {
+    map.iter()
+        .filter(|(_, &value)| value)
+        .filter_map(|(key, _)| key.strip_prefix(prefix)?.parse::<u32>().ok())
+        .filter(|&bit| bit < u128::BITS)
+        .fold(0, |acc, bit| acc | 1 << bit)
 }
 
-fn evaluate_all(gates: &Vec<Gate>, map: &mut HashMap<[char; 3], bool>) {
-    let mut remaining: VecDeque<usize> = (0..gates.len()).collect();
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum AdderRule {
+    HalfSum,   // x XOR y of the bit is missing
+    HalfCarry, // x AND y of the bit is missing
+    SumGate,   // no XOR combines the half sum with the incoming carry
+    SumOutput, // the sum XOR does not drive the z wire of the bit
+    CarryAnd,  // no AND combines the half sum with the incoming carry
+    CarryOr,   // no OR combines the two carry terms
+    CarryOut,  // carry out of the last bit does not drive the top z wire
+}
 
-    // Evaluate gates until all are resolved
-    while !remaining.is_empty() {
-        let i = remaining.pop_front().unwrap();
-        let gate = &gates[i];
-        if map.contains_key(&gate.left) && map.contains_key(&gate.right) {
-            // Evaluate gate
-            eval_gate(gate, map);
-        } else {
-            // Not ready yet, put it back
-            remaining.push_back(i);
+#[derive(PartialEq, Clone, Debug)]
+struct Mismatch {
+    bit: usize,
+    wire: String,
+    rule: AdderRule,
+}
+
+// Structural check of a netlist against a canonical ripple-carry adder:
+//   z_i = (x_i XOR y_i) XOR c_(i-1)
+//   c_i = (x_i AND y_i) OR ((x_i XOR y_i) AND c_(i-1))
+struct AdderChecker<'a> {
+    netlist: &'a Netlist,
+    uses: HashMap<(char, &'a str), Vec<usize>>,
+}
+
+impl<'a> AdderChecker<'a> {
+    fn new(netlist: &'a Netlist) -> AdderChecker<'a> {
+        let mut uses: HashMap<(char, &str), Vec<usize>> = HashMap::new();
+        for (i, gate) in netlist.gates.iter().enumerate() {
+            uses.entry((gate.cmd, &gate.left)).or_default().push(i);
+            if gate.right != gate.left {
+                uses.entry((gate.cmd, &gate.right)).or_default().push(i);
+            }
         }
+        AdderChecker { netlist, uses }
     }
-}
 
-// Name gates based on the structure of ripple-carry adder
-fn name_gates(gates: &Vec<Gate>) -> HashMap<[char; 3], String> {
-    let mut names = HashMap::new();
-    gates.iter().for_each(|g| {
-        if g.left[0] == 'x' || g.left[0] == 'y' {
-            let index = g.left[1..]
-                .iter()
-                .collect::<String>()
-                .parse::<usize>()
-                .unwrap();
-            names.insert(g.target, format!("{}{:02}", g.cmd, index));
+    // Gate of given type reading both wires, otherwise one reading either of them
+    fn find_gate(&self, cmd: char, a: Option<&str>, b: Option<&str>) -> (Option<&'a Gate>, bool) {
+        let candidates = [a, b]
+            .into_iter()
+            .flatten()
+            .flat_map(|w| self.uses.get(&(cmd, w)).into_iter().flatten())
+            .map(|&i| &self.netlist.gates[i])
+            .collect_vec();
+        let uses_wire = |g: &Gate, w: Option<&str>| w.is_some_and(|w| g.left == w || g.right == w);
+        match candidates
+            .iter()
+            .find(|g| uses_wire(g, a) && uses_wire(g, b))
+        {
+            Some(gate) => (Some(*gate), true),
+            None => (candidates.first().copied(), false),
         }
-    });
+    }
+
+    // Check every bit, recovering the carry chain where possible
+    fn verify(&self) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let mut fail = |bit: usize, wire: &str, rule: AdderRule| {
+            mismatches.push(Mismatch {
+                bit,
+                wire: wire.to_string(),
+                rule,
+            });
+        };
+        let n = self.netlist.input_bits();
+        let mut carry: Option<&str> = None;
 
-    fn iterate_gate(g: &Gate, names: &mut HashMap<[char; 3], String>) {
-        let left = names.get(&g.left).map_or("---", |v| v);
-        let right = names.get(&g.right).map_or("---", |v| v);
-        if g.cmd == 'O' {
-            if left.starts_with('A') {
-                names.insert(g.target, format!("c{:02}", &left[1..]));
-            } else if right.starts_with('A') {
-                names.insert(g.target, format!("c{:02}", &right[1..]));
-            } else if left.starts_with('d') {
-                names.insert(g.target, format!("c{:02}", &left[1..]));
-            } else if right.starts_with('d') {
-                names.insert(g.target, format!("c{:02}", &right[1..]));
+        for bit in 0..n {
+            let x = format!("x{:02}", bit);
+            let y = format!("y{:02}", bit);
+            let z = format!("z{:02}", bit);
+            let half_sum = match self.find_gate('X', Some(&x), Some(&y)) {
+                (Some(g), true) => Some(g.target.as_str()),
+                _ => {
+                    fail(bit, &x, AdderRule::HalfSum);
+                    None
+                }
+            };
+            let half_carry = match self.find_gate('A', Some(&x), Some(&y)) {
+                (Some(g), true) => Some(g.target.as_str()),
+                _ => {
+                    fail(bit, &x, AdderRule::HalfCarry);
+                    None
+                }
+            };
+
+            if bit == 0 {
+                if let Some(s) = half_sum.filter(|&s| s != z) {
+                    fail(bit, s, AdderRule::SumOutput);
+                }
+                carry = half_carry;
+                continue;
             }
-        } else if g.cmd == 'A' {
-            if left.starts_with('X') {
-                names.insert(g.target, format!("d{:02}", &left[1..]));
-            } else if right.starts_with('X') {
-                names.insert(g.target, format!("d{:02}", &right[1..]));
-            } else if left.starts_with('c') {
-                names.insert(
-                    g.target,
-                    format!("d{:02}", &left[1..].parse::<usize>().unwrap() + 1),
-                );
-            } else if right.starts_with('c') {
-                names.insert(
-                    g.target,
-                    format!("d{:02}", &right[1..].parse::<usize>().unwrap() + 1),
-                );
+
+            // Sum gate: the input that is not shared with the found gate is misplaced
+            let (sum_gate, exact) = self.find_gate('X', half_sum, carry);
+            match sum_gate {
+                Some(g) if !exact => {
+                    let expected = [half_sum, carry].into_iter().flatten();
+                    for wire in expected.filter(|w| g.left != *w && g.right != *w) {
+                        fail(bit, wire, AdderRule::SumGate);
+                    }
+                }
+                None => fail(bit, half_sum.or(carry).unwrap_or(&z), AdderRule::SumGate),
+                _ => {}
             }
-        } else if g.cmd == 'X' {
-            if right.starts_with('X') {
-                names.insert(g.target, format!("z{:02}", &right[1..]));
-            } else if left.starts_with('X') {
-                names.insert(g.target, format!("z{:02}", &left[1..]));
-            } else if (left == "A00" && right == "X01") || (right == "A00" && left == "X01") {
-                names.insert(g.target, "z01".to_string());
-            } else if left.starts_with('c') {
-                names.insert(
-                    g.target,
-                    format!("z{:02}", &left[1..].parse::<usize>().unwrap() + 1),
-                );
-            } else if right.starts_with('c') {
-                names.insert(
-                    g.target,
-                    format!("z{:02}", &right[1..].parse::<usize>().unwrap() + 1),
-                );
+            if let Some(g) = sum_gate.filter(|g| g.target != z) {
+                fail(bit, &g.target, AdderRule::SumOutput);
             }
+
+            let (and_gate, exact) = self.find_gate('A', half_sum, carry);
+            if !exact {
+                fail(bit, carry.or(half_sum).unwrap_or(&z), AdderRule::CarryAnd);
+            }
+            let carry_term = and_gate.map(|g| g.target.as_str());
+
+            let (or_gate, exact) = self.find_gate('O', half_carry, carry_term);
+            match or_gate {
+                Some(g) if !exact => {
+                    let expected = [half_carry, carry_term].into_iter().flatten();
+                    for wire in expected.filter(|w| g.left != *w && g.right != *w) {
+                        fail(bit, wire, AdderRule::CarryOr);
+                    }
+                }
+                None => fail(
+                    bit,
+                    half_carry.or(carry_term).unwrap_or(&z),
+                    AdderRule::CarryOr,
+                ),
+                _ => {}
+            }
+            carry = or_gate.map(|g| g.target.as_str());
+        }
+
+        let top = format!("z{:02}", n);
+        match carry {
+            Some(c) if c == top => {}
+            Some(c) => fail(n, c, AdderRule::CarryOut),
+            None => fail(n, &top, AdderRule::CarryOut),
         }
+        mismatches
     }
+}
 
-    // Iterate this multiple times to get new names from previous rounds
-    (0..3).for_each(|_| {
-        gates.iter().for_each(|g| {
-            iterate_gate(g, &mut names);
-        });
-    });
-    names
-}
-
-#[allow(dead_code)]
-fn gate_with_names(gate: &Gate, names: &HashMap<[char; 3], String>) -> String {
-    let og_target = gate.target.iter().collect();
-    let og_left = gate.left.iter().collect();
-    let og_right = gate.right.iter().collect();
-
-    let gate_name = names.get(&gate.target).unwrap_or(&og_target);
-    let left_name = names.get(&gate.left).unwrap_or(&og_left);
-    let right_name = names.get(&gate.right).unwrap_or(&og_right);
-    format!(
-        "Gate {}: {} {} {}, originally {}: {} {} {}",
-        gate_name, left_name, gate.cmd, right_name, og_target, og_left, gate.cmd, og_right
-    )
-}
-
-fn test_gates(input: &(HashMap<[char; 3], bool>, Vec<Gate>), n: usize) -> Vec<String> {
-    let gates = &input.1;
-    let names = name_gates(gates);
-    let mut errors = HashSet::new();
-
-    // Optionally print all gates with names
-    /*gates.iter().for_each(|g| {
-        let gate_desc = gate_with_names(g, &names);
-        //if names.get(&g.target).is_none() {
-        //    println!("Gate has no name: {}", gate_desc);
-        //}
-        println!("{}", gate_desc);
-    });*/
-
-    (0..n).for_each(|i| {
-        let default_name = "".to_string();
-        let real_name = names
-            .get(&[
-                'z',
-                format!("{:02}", i).chars().nth(0).unwrap(),
-                format!("{:02}", i).chars().nth(1).unwrap(),
-            ])
-            .unwrap_or(&default_name);
-
-        // Test z gate names
-        if real_name.len() > 0 && i > 0 && format!("z{:02}", i) != *real_name {
-            println!("Gate z{:02} has unexpected name {}", i, real_name);
-            errors.insert(format!("z{:02}", i));
-            errors.insert(real_name.clone());
+// Small xorshift generator for simulation inputs
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+// Input pair where the circuit did not produce x + y
+#[derive(PartialEq, Clone, Debug)]
+struct TestFailure {
+    x: u64,
+    y: u64,
+    expected: u128,
+    actual: Result<u128, NetlistError>,
+    wrong_bits: Vec<usize>,
+}
+
+fn input_mask(bits: usize) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+// Random x/y pairs fitting in the given number of bits
+fn random_vectors(bits: usize, count: usize, seed: u64) -> Vec<(u64, u64)> {
+    let mask = input_mask(bits);
+    let mut rng = XorShift(seed.max(1));
+    (0..count)
+        .map(|_| (rng.next() & mask, rng.next() & mask))
+        .collect()
+}
+
+// All combinations of x, y and incoming carry for every bit position
+fn exhaustive_vectors(bits: usize) -> Vec<(u64, u64)> {
+    (0..bits.min(64))
+        .flat_map(|bit| {
+            let carries: &[u64] = if bit == 0 { &[0] } else { &[0, 1] };
+            carries.iter().flat_map(move |&carry| {
+                // Setting both lower bits produces a carry into this bit
+                let low = (carry << bit) >> 1;
+                (0..4u64).map(move |xy| (((xy & 1) << bit) | low, ((xy >> 1) << bit) | low))
+            })
+        })
+        .collect()
+}
+
+// Run input pairs through the circuit, reporting those where z != x + y
+fn run_vectors(netlist: &Netlist, vectors: &[(u64, u64)]) -> Vec<TestFailure> {
+    let outputs = netlist.input_bits() + 1;
+    vectors
+        .iter()
+        .filter_map(|&(x, y)| {
+            let expected = x as u128 + y as u128;
+            let actual = netlist.evaluate_xy(x, y);
+            let wrong_bits: Vec<usize> = match actual {
+                Ok(z) => (0..outputs)
+                    .filter(|&b| (z ^ expected) >> b & 1 == 1)
+                    .collect(),
+                Err(_) => (0..outputs).collect(),
+            };
+            (!wrong_bits.is_empty()).then_some(TestFailure {
+                x,
+                y,
+                expected,
+                actual,
+                wrong_bits,
+            })
+        })
+        .collect()
+}
+
+// Print which output bits failed and an example input for each
+fn print_failures(failures: &[TestFailure]) {
+    let by_bit = failures
+        .iter()
+        .flat_map(|f| f.wrong_bits.iter().map(move |&b| (b, f)))
+        .into_group_map();
+    for (bit, cases) in by_bit.into_iter().sorted_by_key(|(b, _)| *b) {
+        let f = cases[0];
+        let actual = match &f.actual {
+            Ok(z) => format!("{z:#x}"),
+            Err(e) => e.to_string(),
+        };
+        println!(
+            "z{:02} wrong for {} inputs, e.g. x={:#x} y={:#x}: expected {:#x}, got {}",
+            bit,
+            cases.len(),
+            f.x,
+            f.y,
+            f.expected,
+            actual
+        );
+    }
+}
+
+const MAX_SWAPS: usize = 4;
+
+// Search output swaps that turn the netlist into a working adder
+fn find_swaps(netlist: &Netlist, max_swaps: usize) -> Option<Vec<(String, String)>> {
+    fn failing_bits(netlist: &Netlist) -> Vec<usize> {
+        let mismatches = AdderChecker::new(netlist).verify();
+        mismatches.iter().map(|m| m.bit).dedup().collect()
+    }
+
+    // Gate outputs near one bit of the adder: up to three gates downstream of its inputs
+    // or those of the previous bit, which covers the incoming carry
+    fn region(netlist: &Netlist, bit: usize) -> Vec<String> {
+        let mut wires: HashSet<String> = (bit.saturating_sub(1)..=bit)
+            .flat_map(|b| [format!("x{:02}", b), format!("y{:02}", b)])
+            .collect();
+        for _ in 0..3 {
+            let next = netlist
+                .gates
+                .iter()
+                .filter(|g| wires.contains(&g.left) || wires.contains(&g.right))
+                .map(|g| g.target.clone())
+                .collect_vec();
+            wires.extend(next);
         }
+        wires.insert(format!("z{:02}", bit));
+        netlist
+            .gates
+            .iter()
+            .map(|g| g.target.clone())
+            .filter(|w| wires.contains(w))
+            .sorted()
+            .collect()
+    }
 
-        // Validate c__ gate
-        let c_gate = gates.iter().find(|g| {
-            let left = names.get(&g.left);
-            let right = names.get(&g.right);
-            g.cmd == 'O'
-                && (left == Some(&format!("A{:02}", i))
-                    || right == Some(&format!("A{:02}", i))
-                    || left == Some(&format!("d{:02}", i))
-                    || right == Some(&format!("d{:02}", i)))
-        });
-        if let Some(c) = c_gate {
-            let default = "---".to_string();
-            let left = names.get(&c.left).unwrap_or(&default);
-            let right = names.get(&c.right).unwrap_or(&default);
-            if (left == &format!("A{:02}", i) && right == &format!("d{:02}", i))
-                || (right == &format!("A{:02}", i) && left == &format!("d{:02}", i))
-            {
-                // Valid
-            } else if left == &format!("A{:02}", i) {
-                // A__ is valid, d__ is missing
-                let missing = format!("d{:02}", i);
-                errors.insert(right.clone());
-                errors.insert(missing);
-            } else if left == &format!("d{:02}", i) {
-                // d__ is valid, A__ is missing
-                let missing = format!("A{:02}", i);
-                errors.insert(right.clone());
-                errors.insert(missing);
-            } else if right == &format!("A{:02}", i) {
-                // A__ is valid, d__ is missing
-                let missing = format!("d{:02}", i);
-                errors.insert(left.clone());
-                errors.insert(missing);
-            } else if right == &format!("d{:02}", i) {
-                // d__ is valid, A__ is missing
-                let missing = format!("A{:02}", i);
-                errors.insert(left.clone());
-                errors.insert(missing);
+    fn search(netlist: &Netlist, swaps: &mut Vec<(String, String)>, max_swaps: usize) -> bool {
+        let failing = failing_bits(netlist);
+        let Some(&bit) = failing.first() else {
+            let vectors = random_vectors(netlist.input_bits(), 100, 0x2024_1224);
+            return run_vectors(netlist, &vectors).is_empty();
+        };
+        if swaps.len() == max_swaps {
+            return false;
+        }
+        // Try pairs within the region first, then region wires with any other output
+        let nearby = region(netlist, bit);
+        let distant = netlist
+            .gates
+            .iter()
+            .map(|g| g.target.clone())
+            .filter(|w| !nearby.contains(w))
+            .sorted()
+            .collect_vec();
+        let pairs = nearby
+            .iter()
+            .tuple_combinations()
+            .chain(nearby.iter().cartesian_product(distant.iter()));
+        for (a, b) in pairs {
+            let swap = (a.clone(), b.clone());
+            let Ok(candidate) = netlist.with_swaps(std::slice::from_ref(&swap)) else {
+                continue;
+            };
+            // The swap must repair the failing bit without breaking others
+            let remaining = failing_bits(&candidate);
+            if remaining.len() >= failing.len() || remaining.first().is_some_and(|&f| f <= bit) {
+                continue;
+            }
+            swaps.push(swap);
+            if search(&candidate, swaps, max_swaps) {
+                return true;
             }
+            swaps.pop();
         }
-    });
+        false
+    }
 
-    let mut result = errors
-        .into_iter()
-        .map(|a| {
-            names
-                .iter()
-                .find(|(_, v)| **v == *a)
-                .map(|(k, _)| k.iter().collect::<String>())
-                .unwrap_or("---".to_string())
-        })
+    let mut swaps = Vec::new();
+    search(netlist, &mut swaps, max_swaps).then_some(swaps)
+}
+
+// Wires involved in the repairing swaps
+fn swapped_wires(swaps: &[(String, String)]) -> HashSet<String> {
+    swaps
+        .iter()
+        .flat_map(|(a, b)| [a.clone(), b.clone()])
+        .collect()
+}
+
+// Graphviz graph with a node per wire and per gate, highlighting the given wires
+fn to_dot(netlist: &Netlist, highlight: &HashSet<String>) -> String {
+    let mut out = String::from("digraph circuit {\n    rankdir=LR;\n");
+    let wires = netlist
+        .inputs
+        .iter()
+        .chain(netlist.gates.iter().map(|g| &g.target))
+        .sorted();
+    for wire in wires {
+        let fill = match wire.chars().next() {
+            Some('x') | Some('y') => "lightgrey",
+            Some('z') => "lightyellow",
+            _ => "white",
+        };
+        let extra = if highlight.contains(wire) {
+            ", color=red, penwidth=3"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "    \"{wire}\" [shape=ellipse, style=filled, fillcolor={fill}{extra}];"
+        )
+        .unwrap();
+    }
+    for (i, gate) in netlist.gates.iter().enumerate() {
+        let label = cmd_name(gate.cmd);
+        let fill = match gate.cmd {
+            'A' => "lightblue",
+            'O' => "palegreen",
+            _ => "orange",
+        };
+        writeln!(
+            out,
+            "    g{i} [label=\"{label}\", shape=box, style=filled, fillcolor={fill}];"
+        )
+        .unwrap();
+        writeln!(out, "    \"{}\" -> g{i};", gate.left).unwrap();
+        writeln!(out, "    \"{}\" -> g{i};", gate.right).unwrap();
+        writeln!(out, "    g{i} -> \"{}\";", gate.target).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+// Structural Verilog module built from gate primitives
+fn to_verilog(netlist: &Netlist, module: &str) -> String {
+    let outputs = netlist
+        .gates
+        .iter()
+        .map(|g| &g.target)
+        .filter(|w| w.starts_with('z'))
+        .sorted()
         .collect_vec();
-    result.sort();
-    result
+    let internal = netlist
+        .gates
+        .iter()
+        .map(|g| &g.target)
+        .filter(|w| !w.starts_with('z'))
+        .sorted()
+        .collect_vec();
+    let ports = netlist
+        .inputs
+        .iter()
+        .map(|w| format!("input {w}"))
+        .chain(outputs.iter().map(|w| format!("output {w}")))
+        .join(",\n    ");
+
+    let mut out = format!("module {module}(\n    {ports}\n);\n");
+    for wire in internal {
+        writeln!(out, "    wire {wire};").unwrap();
+    }
+    for &i in &netlist.order {
+        let gate = &netlist.gates[i];
+        let primitive = match gate.cmd {
+            'A' => "and",
+            'O' => "or",
+            'X' => "xor",
+            _ => panic!("Unknown command"),
+        };
+        writeln!(
+            out,
+            "    {primitive} g_{}({}, {}, {});",
+            gate.target, gate.target, gate.left, gate.right
+        )
+        .unwrap();
+    }
+    out.push_str("endmodule\n");
+    out
 }
 
-fn part1(input: &(HashMap<[char; 3], bool>, Vec<Gate>)) -> usize {
-    let mut map = input.0.clone();
-    let gates = &input.1;
+fn part1(input: &(HashMap<String, bool>, Netlist)) -> u128 {
+    let (initial, netlist) = input;
 
     // Evaluate all gates
-    evaluate_all(gates, &mut map);
+    let map = netlist
+        .evaluate(initial)
+        .unwrap_or_else(|e| panic!("Evaluation failed: {e}"));
+
+    get_number('z', &map)
+}
+
+// Netlist with swaps applied, checked structurally and by simulation
+struct Repair {
+    swaps: Vec<(String, String)>,
+    netlist: Netlist,
+}
+
+fn repair_adder(netlist: &Netlist, max_swaps: usize) -> Option<Repair> {
+    let swaps = find_swaps(netlist, max_swaps)?;
+    let repaired = netlist.with_swaps(&swaps).ok()?;
+
+    let bits = repaired.input_bits();
+    let mut vectors = exhaustive_vectors(bits);
+    vectors.extend(random_vectors(bits, 1000, 0x2024_1224));
+    let verified = AdderChecker::new(&repaired).verify().is_empty()
+        && run_vectors(&repaired, &vectors).is_empty();
+    verified.then_some(Repair {
+        swaps,
+        netlist: repaired,
+    })
+}
 
-    get_number("z", &map)
+// Lines in the puzzle's input format: initial values, blank line, gates
+fn to_puzzle_format(initial: &HashMap<String, bool>, netlist: &Netlist) -> Vec<String> {
+    let values = initial
+        .iter()
+        .sorted()
+        .map(|(wire, &value)| format!("{wire}: {}", value as u8));
+    let gates = netlist.gates.iter().map(|g| {
+        let cmd = cmd_name(g.cmd);
+        format!("{} {cmd} {} -> {}", g.left, g.right, g.target)
+    });
+    values.chain([String::new()]).chain(gates).collect()
 }
 
-fn part2(input: &(HashMap<[char; 3], bool>, Vec<Gate>), test: bool) -> String {
-    let n = if test { 6 } else { 45 };
-    let result = test_gates(input, n);
-    result.join(",")
+// Swapped wires, sorted and joined by commas
+fn part2(repair: &Repair) -> String {
+    swapped_wires(&repair.swaps).into_iter().sorted().join(",")
 }
 
 #[cfg(test)]
@@ -310,7 +771,7 @@ mod tests {
     use super::*;
 
     // Parse sample input
-    fn sample_input() -> (HashMap<[char; 3], bool>, Vec<Gate>) {
+    fn sample_input() -> (HashMap<String, bool>, Netlist) {
         let data = "x00: 1
 x01: 1
 x02: 1
@@ -327,7 +788,7 @@ x02 OR y02 -> z02"
         process_input(data)
     }
 
-    fn sample_input_large() -> (HashMap<[char; 3], bool>, Vec<Gate>) {
+    fn sample_input_large() -> (HashMap<String, bool>, Netlist) {
         let data = "x00: 1
 x01: 0
 x02: 1
@@ -384,19 +845,242 @@ tnw OR pbm -> gnj"
     #[test]
     fn test_something() {
         let input = day_input();
-        let mut map = input.0;
-        let gates = &input.1;
         // Evaluate all gates
-        evaluate_all(gates, &mut map);
+        let map = input.1.evaluate(&input.0).unwrap();
 
-        let x = get_number("x", &map);
-        let y = get_number("y", &map);
-        let z = get_number("z", &map);
+        let x = get_number('x', &map);
+        let y = get_number('y', &map);
+        let z = get_number('z', &map);
         let target = x & y;
         println!("z: {:b}", z);
         println!("t: 0{:b}", target);
     }
 
+    fn gates_from(lines: &[&str]) -> Vec<Gate> {
+        let data: Vec<String> = lines.iter().map(|s| s.to_string()).collect();
+        parse_lines(&data).1
+    }
+
+    #[test]
+    fn test_netlist_order() {
+        let (_, netlist) = sample_input_large();
+        let position: HashMap<&str, usize> = netlist
+            .order
+            .iter()
+            .enumerate()
+            .map(|(pos, &i)| (netlist.gates[i].target.as_str(), pos))
+            .collect();
+        // Every gate comes after the gates driving its inputs
+        for (pos, &i) in netlist.order.iter().enumerate() {
+            let gate = &netlist.gates[i];
+            for wire in [&gate.left, &gate.right] {
+                assert!(position.get(wire.as_str()).is_none_or(|&p| p < pos));
+            }
+        }
+    }
+
+    #[test]
+    fn test_netlist_errors() {
+        let gates = gates_from(&[
+            "x00 AND abc -> def",
+            "def OR y00 -> abc",
+            "abc XOR x00 -> z00",
+        ]);
+        let inputs = ["x00".to_string(), "y00".to_string()];
+        assert_eq!(
+            Err(NetlistError::Cycle(vec![
+                "abc".to_string(),
+                "def".to_string()
+            ])),
+            Netlist::new(inputs.clone(), gates).map(|_| ())
+        );
+
+        let gates = gates_from(&["x00 AND foo -> z00"]);
+        assert_eq!(
+            Err(NetlistError::Undriven("foo".to_string())),
+            Netlist::new(inputs.clone(), gates).map(|_| ())
+        );
+
+        let gates = gates_from(&["x00 AND y00 -> z00", "x00 OR y00 -> z00"]);
+        assert_eq!(
+            Err(NetlistError::MultipleDrivers("z00".to_string())),
+            Netlist::new(inputs.clone(), gates).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_evaluate_xy() {
+        let (_, netlist) = sample_input();
+        // z00 = x00 AND y00, z01 = x01 XOR y01, z02 = x02 OR y02
+        assert_eq!(Ok(0b111), netlist.evaluate_xy(0b101, 0b011));
+        assert_eq!(Ok(0b100), netlist.evaluate_xy(0b010, 0b110));
+        assert_eq!(Ok(0b101), netlist.evaluate_xy(u64::MAX, u64::MAX));
+        assert_eq!(
+            Err(NetlistError::MissingInput("x00".to_string())),
+            netlist.evaluate(&HashMap::new()).map(|_| ())
+        );
+    }
+
+    // Canonical ripple-carry adder with all inputs set to zero
+    fn adder_lines(bits: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        for i in 0..bits {
+            lines.push(format!("x{:02}: 0", i));
+            lines.push(format!("y{:02}: 0", i));
+        }
+        lines.push(String::new());
+        lines.push("x00 XOR y00 -> z00".to_string());
+        lines.push("x00 AND y00 -> c00".to_string());
+        for i in 1..bits {
+            let carry = if i == bits - 1 {
+                format!("z{:02}", bits)
+            } else {
+                format!("c{:02}", i)
+            };
+            lines.push(format!("x{i:02} XOR y{i:02} -> s{i:02}"));
+            lines.push(format!("y{i:02} AND x{i:02} -> a{i:02}"));
+            lines.push(format!("c{:02} XOR s{i:02} -> z{i:02}", i - 1));
+            lines.push(format!("s{i:02} AND c{:02} -> d{i:02}", i - 1));
+            lines.push(format!("a{i:02} OR d{i:02} -> {carry}"));
+        }
+        lines
+    }
+
+    fn adder_with_swaps(bits: usize, swaps: &[(&str, &str)]) -> Netlist {
+        let swaps = swaps
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect_vec();
+        process_input(adder_lines(bits))
+            .1
+            .with_swaps(&swaps)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_verify_adder() {
+        let netlist = adder_with_swaps(8, &[]);
+        assert!(AdderChecker::new(&netlist).verify().is_empty());
+        assert!(run_vectors(&netlist, &random_vectors(8, 100, 1)).is_empty());
+
+        let netlist = adder_with_swaps(8, &[("z05", "c05")]);
+        let mismatches = AdderChecker::new(&netlist).verify();
+        assert_eq!(
+            Some(&Mismatch {
+                bit: 5,
+                wire: "c05".to_string(),
+                rule: AdderRule::SumOutput
+            }),
+            mismatches.first()
+        );
+        assert!(!run_vectors(&netlist, &random_vectors(8, 100, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_find_swaps() {
+        let netlist = adder_with_swaps(12, &[("z03", "d03"), ("s07", "a07")]);
+        let swaps = find_swaps(&netlist, 4).unwrap();
+        let wires = swaps
+            .into_iter()
+            .flat_map(|(a, b)| [a, b])
+            .sorted()
+            .join(",");
+        assert_eq!("a07,d03,s07,z03", wires);
+        assert_eq!(None, find_swaps(&netlist, 1));
+    }
+
+    #[test]
+    fn test_run_vectors() {
+        let netlist = adder_with_swaps(64, &[]);
+        assert_eq!(
+            Ok(u64::MAX as u128 * 2),
+            netlist.evaluate_xy(u64::MAX, u64::MAX)
+        );
+        assert!(run_vectors(&netlist, &random_vectors(64, 20, 7)).is_empty());
+
+        // Exchanging the sum and carry terms of bit 4 breaks z04 and the carries above it
+        let netlist = adder_with_swaps(8, &[("s04", "a04")]);
+        let vectors = exhaustive_vectors(8);
+        assert_eq!(8 * 8 - 4, vectors.len());
+        let failures = run_vectors(&netlist, &vectors);
+        let wrong = failures
+            .iter()
+            .flat_map(|f| f.wrong_bits.clone())
+            .sorted()
+            .dedup()
+            .collect_vec();
+        assert_eq!(vec![4, 5, 6], wrong);
+        assert!(failures.contains(&TestFailure {
+            x: 1 << 4,
+            y: 0,
+            expected: 1 << 4,
+            actual: Ok(1 << 5),
+            wrong_bits: vec![4, 5],
+        }));
+    }
+
+    #[test]
+    fn test_repair_adder() {
+        let data = adder_lines(10);
+        let (initial, netlist) = process_input(data.clone());
+        let broken = netlist
+            .with_swaps(&[("z02".to_string(), "d02".to_string())])
+            .unwrap();
+        assert!(repair_adder(&broken, 0).is_none());
+        assert_eq!("d02,z02", part2(&repair_adder(&broken, 1).unwrap()));
+
+        // Repairing restores the original circuit, so the output matches the input lines
+        let repair = repair_adder(&broken, 4).unwrap();
+        let sorted_input = data.iter().take(20).sorted().chain(data.iter().skip(20));
+        assert_eq!(
+            sorted_input.cloned().collect_vec(),
+            to_puzzle_format(&initial, &repair.netlist)
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let (_, netlist) = sample_input();
+        let highlight = HashSet::from(["z01".to_string()]);
+        let dot = to_dot(&netlist, &highlight);
+        assert!(dot.starts_with("digraph circuit {"));
+        assert!(dot.contains("\"x00\" [shape=ellipse, style=filled, fillcolor=lightgrey];"));
+        assert!(dot.contains(
+            "\"z01\" [shape=ellipse, style=filled, fillcolor=lightyellow, color=red, penwidth=3];"
+        ));
+        assert!(dot.contains("g1 [label=\"XOR\", shape=box, style=filled, fillcolor=orange];"));
+        assert!(dot.contains("\"y01\" -> g1;"));
+        assert!(dot.contains("g1 -> \"z01\";"));
+    }
+
+    #[test]
+    fn test_to_verilog() {
+        let netlist = adder_with_swaps(2, &[]);
+        let expected = "module adder(
+    input x00,
+    input x01,
+    input y00,
+    input y01,
+    output z00,
+    output z01,
+    output z02
+);
+    wire a01;
+    wire c00;
+    wire d01;
+    wire s01;
+    xor g_z00(z00, x00, y00);
+    and g_c00(c00, x00, y00);
+    xor g_s01(s01, x01, y01);
+    and g_a01(a01, y01, x01);
+    xor g_z01(z01, c00, s01);
+    and g_d01(d01, s01, c00);
+    or g_z02(z02, a01, d01);
+endmodule
+";
+        assert_eq!(expected, to_verilog(&netlist, "adder"));
+    }
+
     #[test]
     fn test_part1() {
         let input = sample_input();
@@ -418,6 +1102,7 @@ tnw OR pbm -> gnj"
     #[test]
     fn real_part2() {
         let input = day_input();
-        assert_eq!("fgt,fpq,nqk,pcp,srn,z07,z24,z32", part2(&input, false));
+        let repair = repair_adder(&input.1, MAX_SWAPS).unwrap();
+        assert_eq!("fgt,fpq,nqk,pcp,srn,z07,z24,z32", part2(&repair));
     }
 }
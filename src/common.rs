@@ -9,3 +9,15 @@ pub fn read_input(day: u32) -> Vec<String> {
     let reader = BufReader::new(file);
     reader.lines().map(|l| l.unwrap()).collect()
 }
+
+// Check whether a command line flag such as `--render` was given
+pub fn has_flag(name: &str) -> bool {
+    std::env::args().any(|a| a == name)
+}
+
+// Value following a command line option, e.g. `--dot out.dot`
+pub fn arg_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|a| a != name).nth(1)
+}
+
+pub mod graph;
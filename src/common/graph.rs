@@ -0,0 +1,257 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+// Graph over interned vertex names. Vertices are numbered in insertion order;
+// edges are kept both as adjacency lists and as bitset rows for O(1) lookups.
+pub struct Graph<N> {
+    names: Vec<N>,
+    ids: HashMap<N, usize>,
+    outgoing: Vec<Vec<usize>>,
+    incoming: Vec<Vec<usize>>,
+    bits: Vec<Vec<u64>>,
+    directed: bool,
+}
+
+impl<N: Eq + Hash + Clone> Graph<N> {
+    pub fn directed() -> Graph<N> {
+        Graph::new(true)
+    }
+
+    pub fn undirected() -> Graph<N> {
+        Graph::new(false)
+    }
+
+    fn new(directed: bool) -> Graph<N> {
+        Graph {
+            names: Vec::new(),
+            ids: HashMap::new(),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            bits: Vec::new(),
+            directed,
+        }
+    }
+
+    // Id of a vertex, adding it if it is new
+    pub fn intern(&mut self, name: N) -> usize {
+        if let Some(&id) = self.ids.get(&name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.clone());
+        self.ids.insert(name, id);
+        self.outgoing.push(Vec::new());
+        self.incoming.push(Vec::new());
+        let words = (id + 1).div_ceil(64);
+        if words > self.bits.first().map_or(0, |row| row.len()) {
+            self.bits.iter_mut().for_each(|row| row.resize(words, 0));
+        }
+        self.bits.push(vec![0; words]);
+        id
+    }
+
+    pub fn id(&self, name: &N) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, v: usize) -> &N {
+        &self.names[v]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    // Add an edge between two names, interning them; duplicates are ignored
+    pub fn add_edge(&mut self, from: N, to: N) -> (usize, usize) {
+        let a = self.intern(from);
+        let b = self.intern(to);
+        self.link(a, b);
+        if !self.directed {
+            self.link(b, a);
+        }
+        (a, b)
+    }
+
+    fn link(&mut self, a: usize, b: usize) {
+        if !self.has_edge(a, b) {
+            self.bits[a][b / 64] |= 1 << (b % 64);
+            self.outgoing[a].push(b);
+            self.incoming[b].push(a);
+        }
+    }
+
+    pub fn has_edge(&self, a: usize, b: usize) -> bool {
+        self.bits[a][b / 64] >> (b % 64) & 1 == 1
+    }
+
+    pub fn neighbors(&self, v: usize) -> &[usize] {
+        &self.outgoing[v]
+    }
+
+    pub fn predecessors(&self, v: usize) -> &[usize] {
+        &self.incoming[v]
+    }
+
+    // Bitset row of the neighbors of a vertex
+    pub fn neighbor_bits(&self, v: usize) -> &[u64] {
+        &self.bits[v]
+    }
+
+    pub fn degree(&self, v: usize) -> usize {
+        self.outgoing[v].len()
+    }
+
+    pub fn in_degree(&self, v: usize) -> usize {
+        self.incoming[v].len()
+    }
+
+    // Triangles of the undirected graph, each as ascending ids
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        let mut triangles = Vec::new();
+        for a in 0..self.len() {
+            for &b in self.outgoing[a].iter().filter(|&&b| b > a) {
+                // Common neighbors above b, straight from the bitset rows
+                for (w, (&x, &y)) in self.bits[a].iter().zip(&self.bits[b]).enumerate() {
+                    let mut common = x & y;
+                    while common != 0 {
+                        let c = w * 64 + common.trailing_zeros() as usize;
+                        common &= common - 1;
+                        if c > b {
+                            triangles.push([a, b, c]);
+                        }
+                    }
+                }
+            }
+        }
+        triangles
+    }
+
+    // Kahn's algorithm on the subgraph induced by `vertices`, keeping their given
+    // order where edges allow. Fails with the vertices that sit on or behind a cycle.
+    pub fn topological_sort(&self, vertices: &[usize]) -> Result<Vec<usize>, Vec<usize>> {
+        let mut member = vec![false; self.len()];
+        vertices.iter().for_each(|&v| member[v] = true);
+        let mut in_degree = vec![0; self.len()];
+        for &v in vertices {
+            in_degree[v] = self.incoming[v].iter().filter(|&&u| member[u]).count();
+        }
+
+        let mut queue: VecDeque<usize> = vertices
+            .iter()
+            .copied()
+            .filter(|&v| in_degree[v] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(vertices.len());
+        while let Some(v) = queue.pop_front() {
+            order.push(v);
+            for &w in self.outgoing[v].iter().filter(|&&w| member[w]) {
+                in_degree[w] -= 1;
+                if in_degree[w] == 0 {
+                    queue.push_back(w);
+                }
+            }
+        }
+
+        if order.len() == vertices.len() {
+            Ok(order)
+        } else {
+            Err(vertices
+                .iter()
+                .copied()
+                .filter(|&v| in_degree[v] > 0)
+                .collect())
+        }
+    }
+
+    // Connected components, ignoring edge direction
+    pub fn connected_components(&self) -> Vec<Vec<usize>> {
+        let mut component = vec![usize::MAX; self.len()];
+        let mut components = Vec::new();
+        for start in 0..self.len() {
+            if component[start] != usize::MAX {
+                continue;
+            }
+            let id = components.len();
+            let mut members = vec![start];
+            component[start] = id;
+            let mut i = 0;
+            while i < members.len() {
+                let v = members[i];
+                for &w in self.outgoing[v].iter().chain(&self.incoming[v]) {
+                    if component[w] == usize::MAX {
+                        component[w] = id;
+                        members.push(w);
+                    }
+                }
+                i += 1;
+            }
+            components.push(members);
+        }
+        components
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undirected() {
+        let mut graph: Graph<&str> = Graph::undirected();
+        for (a, b) in [("a", "b"), ("b", "c"), ("c", "a"), ("c", "d"), ("e", "f")] {
+            graph.add_edge(a, b);
+        }
+        graph.add_edge("b", "a");
+        assert_eq!(6, graph.len());
+        assert_eq!(Some(2), graph.id(&"c"));
+        assert_eq!(&"d", graph.name(3));
+        assert!(graph.has_edge(3, 2) && graph.has_edge(2, 3));
+        assert_eq!(3, graph.degree(2));
+        assert_eq!(2, graph.degree(0));
+        assert_eq!(vec![[0, 1, 2]], graph.triangles());
+        assert_eq!(
+            vec![vec![0, 1, 2, 3], vec![4, 5]],
+            graph.connected_components()
+        );
+    }
+
+    #[test]
+    fn test_many_vertices() {
+        // Rows grow as vertices are added beyond one bitset word
+        let mut graph: Graph<usize> = Graph::undirected();
+        for i in 0..200 {
+            graph.add_edge(i, (i + 1) % 200);
+            graph.add_edge(i, (i + 2) % 200);
+        }
+        assert!(graph.has_edge(199, 0) && !graph.has_edge(0, 100));
+        assert!(graph.neighbor_bits(5).len() == 4);
+        assert_eq!(200, graph.triangles().len());
+        assert_eq!(1, graph.connected_components().len());
+    }
+
+    #[test]
+    fn test_topological_sort() {
+        let mut graph: Graph<u32> = Graph::directed();
+        for (a, b) in [(1, 2), (2, 3), (1, 3), (4, 3), (5, 6), (6, 5)] {
+            graph.add_edge(a, b);
+        }
+        assert!(graph.has_edge(0, 1) && !graph.has_edge(1, 0));
+        assert_eq!(1, graph.in_degree(1));
+        assert_eq!(&[1, 0, 3], graph.predecessors(2));
+        assert_eq!(Ok(vec![0, 3, 1, 2]), graph.topological_sort(&[0, 1, 2, 3]));
+        assert_eq!(Ok(vec![3, 2]), graph.topological_sort(&[2, 3]));
+        assert_eq!(Err(vec![4, 5]), graph.topological_sort(&[0, 4, 5]));
+        assert_eq!(2, graph.connected_components().len());
+    }
+}